@@ -0,0 +1,246 @@
+use crate::diff::{keyed_position, DeepDiff, Diff, Value};
+use crate::path::{parse_path, PathSegment};
+use std::cmp::Reverse;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    PathNotFound(String),
+    Conflict(String),
+    Summarized(String),
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::PathNotFound(path) => write!(f, "no value at path `{}`", path),
+            ApplyError::Conflict(path) => {
+                write!(f, "value at path `{}` does not match the patch", path)
+            }
+            ApplyError::Summarized(path) => {
+                write!(f, "summary at path `{}` cannot be applied", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+/// One step of an idempotent patch: the value expected at `path` before the
+/// change and the value it should hold after. `None` means absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotentOp {
+    pub path: String,
+    pub expected: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Converts diffs to idempotent ops, ordered so they can be applied in
+/// sequence: changes, then removals from the back, then additions from the
+/// front. Summaries are skipped.
+pub fn to_idempotent_patch(diffs: &[Diff]) -> Vec<IdempotentOp> {
+    let mut changes = Vec::new();
+    let mut removals = Vec::new();
+    let mut additions = Vec::new();
+
+    for diff in diffs {
+        let op = |expected: Option<&Value>, new: Option<&Value>| IdempotentOp {
+            path: diff.path().to_string(),
+            expected: expected.cloned(),
+            new: new.cloned(),
+        };
+        match diff {
+            Diff::Added(_, value) => additions.push(op(None, Some(value))),
+            Diff::Removed(_, value) => removals.push(op(Some(value), None)),
+            Diff::Changed(_, old, new)
+            | Diff::StructureChanged(_, old, new)
+            | Diff::TypeChanged(_, old, new)
+            | Diff::NumericChanged(_, old, new, _) => changes.push(op(Some(old), Some(new))),
+            Diff::Summarized(..) | Diff::Reordered(_) => {}
+        }
+    }
+
+    removals.sort_by_cached_key(|op| Reverse(parse_path(&op.path)));
+    additions.sort_by_cached_key(|op| parse_path(&op.path));
+    changes
+        .into_iter()
+        .chain(removals)
+        .chain(additions)
+        .collect()
+}
+
+/// Applies diffs from `compare` to a copy of `base`, reconstructing the
+/// other side. Fails if a path doesn't resolve or a changed or removed value
+/// doesn't match `base`, as with a stale patch. Summaries and `ignore_order`
+/// diffs don't carry enough to be applied.
+pub fn apply(base: &Value, diffs: &[Diff]) -> Result<Value, ApplyError> {
+    if let Some(summary) = diffs
+        .iter()
+        .find(|diff| matches!(diff, Diff::Summarized(..)))
+    {
+        return Err(ApplyError::Summarized(summary.path().to_string()));
+    }
+
+    let mut target = base.clone();
+    for op in to_idempotent_patch(diffs) {
+        let segments = parse_path(&op.path);
+        if !holds_expected(&target, &segments, op.expected.as_ref()) {
+            return Err(match lookup(&target, &segments) {
+                None if op.expected.is_some() => ApplyError::PathNotFound(op.path),
+                _ => ApplyError::Conflict(op.path),
+            });
+        }
+        apply_op(&mut target, &segments, &op)?;
+    }
+
+    Ok(target)
+}
+
+/// Applies `ops` to a copy of `base`. An op whose target already holds the
+/// new value is skipped, so applying the same patch twice is a no-op. A
+/// target holding neither the expected nor the new value is a conflict.
+///
+/// Removing an array element shifts the ones after it, so a repeated array
+/// removal sees a different element and reports a conflict.
+pub fn apply_idempotent(base: &Value, ops: &[IdempotentOp]) -> Result<Value, ApplyError> {
+    let mut target = base.clone();
+
+    for op in ops {
+        let segments = parse_path(&op.path);
+        if lookup(&target, &segments) == op.new.as_ref() {
+            continue;
+        }
+        if !holds_expected(&target, &segments, op.expected.as_ref()) {
+            return Err(ApplyError::Conflict(op.path.clone()));
+        }
+        apply_op(&mut target, &segments, op)?;
+    }
+
+    Ok(target)
+}
+
+impl DeepDiff {
+    /// Checks that applying `compare(v1, v2)` to `v1` rebuilds exactly `v2`.
+    /// Options that hide differences, such as tolerances or `ignore_order`,
+    /// make this fail whenever they hide one.
+    pub fn verify_roundtrip(&self, v1: &Value, v2: &Value) -> bool {
+        apply(v1, &self.compare(v1, v2)).is_ok_and(|rebuilt| rebuilt == *v2)
+    }
+}
+
+// Whether `segments` can take an op expecting `expected`: the value there
+// equals it, or for an insertion, the key is free or the slot is in an array.
+fn holds_expected(target: &Value, segments: &[PathSegment], expected: Option<&Value>) -> bool {
+    match (expected, segments.split_last()) {
+        (Some(expected), _) => lookup(target, segments) == Some(expected),
+        (None, Some((PathSegment::Index(_) | PathSegment::Keyed(..), parents))) => {
+            matches!(lookup(target, parents), Some(Value::Array(_)))
+        }
+        (None, _) => lookup(target, segments).is_none(),
+    }
+}
+
+fn apply_op(
+    target: &mut Value,
+    segments: &[PathSegment],
+    op: &IdempotentOp,
+) -> Result<(), ApplyError> {
+    match (&op.expected, &op.new) {
+        (_, None) => remove_at(target, segments, &op.path),
+        (None, Some(new)) => insert_at(target, segments, new.clone(), &op.path),
+        (Some(_), Some(new)) => replace_at(target, segments, new.clone(), &op.path),
+    }
+}
+
+pub(crate) fn lookup<'v>(value: &'v Value, segments: &[PathSegment]) -> Option<&'v Value> {
+    segments
+        .iter()
+        .try_fold(value, |current, segment| match (current, segment) {
+            (Value::Dict(dict), PathSegment::Key(key)) => dict.get(key),
+            (Value::Array(arr), PathSegment::Index(index)) => arr.get(*index),
+            (Value::Array(arr), PathSegment::Keyed(field, value)) => {
+                keyed_position(arr, field, value).map(|index| &arr[index])
+            }
+            _ => None,
+        })
+}
+
+fn parent_mut<'v>(
+    value: &'v mut Value,
+    segments: &[PathSegment],
+    path: &str,
+) -> Result<&'v mut Value, ApplyError> {
+    segments.iter().try_fold(value, |current, segment| {
+        let next = match (current, segment) {
+            (Value::Dict(dict), PathSegment::Key(key)) => dict.get_mut(key),
+            (Value::Array(arr), PathSegment::Index(index)) => arr.get_mut(*index),
+            (Value::Array(arr), PathSegment::Keyed(field, value)) => {
+                keyed_position(arr, field, value).map(|index| &mut arr[index])
+            }
+            _ => None,
+        };
+        next.ok_or_else(|| ApplyError::PathNotFound(path.to_string()))
+    })
+}
+
+// Adds a new dict entry or inserts into an array, shifting later elements.
+// An element matched by `array_key` has no position, so it is appended.
+pub(crate) fn insert_at(
+    root: &mut Value,
+    segments: &[PathSegment],
+    value: Value,
+    path: &str,
+) -> Result<(), ApplyError> {
+    let (last, parents) = match segments.split_last() {
+        Some(split) => split,
+        None => {
+            *root = value;
+            return Ok(());
+        }
+    };
+    match (parent_mut(root, parents, path)?, last) {
+        (Value::Dict(dict), PathSegment::Key(key)) => {
+            dict.insert(key.clone(), value);
+        }
+        (Value::Array(arr), PathSegment::Index(index)) if *index <= arr.len() => {
+            arr.insert(*index, value)
+        }
+        (Value::Array(arr), PathSegment::Keyed(..)) => arr.push(value),
+        _ => return Err(ApplyError::PathNotFound(path.to_string())),
+    }
+    Ok(())
+}
+
+pub(crate) fn replace_at(
+    root: &mut Value,
+    segments: &[PathSegment],
+    value: Value,
+    path: &str,
+) -> Result<(), ApplyError> {
+    *parent_mut(root, segments, path)? = value;
+    Ok(())
+}
+
+pub(crate) fn remove_at(
+    root: &mut Value,
+    segments: &[PathSegment],
+    path: &str,
+) -> Result<(), ApplyError> {
+    let not_found = || ApplyError::PathNotFound(path.to_string());
+    let (last, parents) = segments.split_last().ok_or_else(not_found)?;
+    match (parent_mut(root, parents, path)?, last) {
+        (Value::Dict(dict), PathSegment::Key(key)) => {
+            dict.remove(key).map(|_| ()).ok_or_else(not_found)
+        }
+        (Value::Array(arr), PathSegment::Index(index)) if *index < arr.len() => {
+            arr.remove(*index);
+            Ok(())
+        }
+        (Value::Array(arr), PathSegment::Keyed(field, value)) => {
+            let index = keyed_position(arr, field, value).ok_or_else(not_found)?;
+            arr.remove(index);
+            Ok(())
+        }
+        _ => Err(not_found()),
+    }
+}
@@ -0,0 +1,58 @@
+use crate::diff::{DeepDiff, Diff, Value};
+
+/// Which kinds of change `DeepDiff::compare_breaking` flags as breaking. By
+/// default removals and type changes are, additions and value changes aren't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakingRules {
+    pub removals: bool,
+    pub type_changes: bool,
+    pub additions: bool,
+    pub value_changes: bool,
+}
+
+impl Default for BreakingRules {
+    fn default() -> Self {
+        BreakingRules {
+            removals: true,
+            type_changes: true,
+            additions: false,
+            value_changes: false,
+        }
+    }
+}
+
+impl BreakingRules {
+    pub fn is_breaking(&self, diff: &Diff) -> bool {
+        match diff {
+            Diff::Added(..) => self.additions,
+            Diff::Removed(..) => self.removals,
+            Diff::StructureChanged(..) | Diff::TypeChanged(..) => self.type_changes,
+            Diff::Changed(..) | Diff::NumericChanged(..) => self.value_changes,
+            Diff::Reordered(_) => false,
+            Diff::Summarized(_, summary) => {
+                (self.additions && summary.added > 0)
+                    || (self.removals && summary.removed > 0)
+                    || summary.sample.iter().any(|diff| self.is_breaking(diff))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedDiff {
+    pub diff: Diff,
+    pub breaking: bool,
+}
+
+impl DeepDiff {
+    pub fn compare_breaking(&self, v1: &Value, v2: &Value) -> Vec<AnnotatedDiff> {
+        let rules = self.breaking_rules();
+        self.compare(v1, v2)
+            .into_iter()
+            .map(|diff| AnnotatedDiff {
+                breaking: rules.is_breaking(&diff),
+                diff,
+            })
+            .collect()
+    }
+}
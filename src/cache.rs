@@ -0,0 +1,49 @@
+use crate::diff::Diff;
+use std::collections::HashMap;
+
+pub(crate) const DEFAULT_CACHE_SIZE: usize = 128;
+
+// A small least-recently-used map from a pair of content hashes to the diffs
+// computed for it. Eviction scans for the oldest entry, which is fine at the
+// sizes this is meant for.
+pub(crate) struct DiffCache {
+    capacity: usize,
+    entries: HashMap<(u64, u64), (Vec<Diff>, u64)>,
+    tick: u64,
+}
+
+impl DiffCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        DiffCache {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: (u64, u64)) -> Option<Vec<Diff>> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries.get_mut(&key).map(|(diffs, used)| {
+            *used = tick;
+            diffs.clone()
+        })
+    }
+
+    pub(crate) fn insert(&mut self, key: (u64, u64), diffs: Vec<Diff>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            if let Some(oldest) = self.entries.iter().min_by_key(|(_, (_, used))| *used).map(|(key, _)| *key) {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (diffs, self.tick));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
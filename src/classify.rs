@@ -0,0 +1,55 @@
+use crate::diff::{Diff, Value};
+use std::cmp::Ordering;
+
+/// The kind of change a diff makes to its leaf, for change-type analytics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeClass {
+    Addition,
+    Removal,
+    IncreaseNumeric,
+    DecreaseNumeric,
+    StringEdit,
+    BoolFlip,
+    TypeChange,
+    Reorder,
+    /// A change that fits no other class, such as numbers equal in value
+    /// but not in representation.
+    Other,
+}
+
+/// Classifies each diff by path. Summaries don't say what changed under
+/// them and are skipped.
+pub fn classify_changes(diffs: &[Diff]) -> Vec<(String, ChangeClass)> {
+    diffs
+        .iter()
+        .filter_map(|diff| {
+            let class = match diff {
+                Diff::Added(..) => ChangeClass::Addition,
+                Diff::Removed(..) => ChangeClass::Removal,
+                Diff::StructureChanged(..) | Diff::TypeChanged(..) => ChangeClass::TypeChange,
+                Diff::Changed(_, old, new) | Diff::NumericChanged(_, old, new, _) => classify_change(old, new),
+                Diff::Reordered(_) => ChangeClass::Reorder,
+                Diff::Summarized(..) => return None,
+            };
+            Some((diff.path().to_string(), class))
+        })
+        .collect()
+}
+
+fn classify_change(old: &Value, new: &Value) -> ChangeClass {
+    let order = match (old, new) {
+        (Value::Int(_) | Value::UInt(_), Value::Int(_) | Value::UInt(_)) => old.as_i128().partial_cmp(&new.as_i128()),
+        (Value::String(_), Value::String(_)) => return ChangeClass::StringEdit,
+        (Value::Bool(_), Value::Bool(_)) => return ChangeClass::BoolFlip,
+        _ => match (old.as_f64(), new.as_f64()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ if old.type_name() != new.type_name() => return ChangeClass::TypeChange,
+            _ => None,
+        },
+    };
+    match order {
+        Some(Ordering::Less) => ChangeClass::IncreaseNumeric,
+        Some(Ordering::Greater) => ChangeClass::DecreaseNumeric,
+        _ => ChangeClass::Other,
+    }
+}
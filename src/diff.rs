@@ -1,19 +1,106 @@
-use serde::Serialize;
+use crate::apply::lookup;
+use crate::breaking::BreakingRules;
+use crate::cache::{DiffCache, DEFAULT_CACHE_SIZE};
+use crate::canonical::to_canonical_bytes;
+use crate::datetime::parse_rfc3339;
+use crate::hash::Fnv1a;
+use crate::iter::DiffIter;
+use crate::path::{
+    element_prefix, is_under, parse_path, path_depth, push_key, with_separator, PathError, PathPattern, PathSegment,
+};
+use crate::sink::{Bounded, Channel, Counted, DiffScore, DiffSink, Filtered, First, Probe, TopN, WithTolerated};
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::borrow::Cow;
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
-use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{SendError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Diff {
     Added(String, Value),
     Removed(String, Value),
     Changed(String, Value, Value),
+    StructureChanged(String, Value, Value),
+    TypeChanged(String, Value, Value),
+    NumericChanged(String, Value, Value, Option<f64>),
+    Summarized(String, DiffSummary),
+    Reordered(String),
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Counts of the diffs found under a dict too wide to report key by key, with
+/// the first few of them kept as a sample.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub sample: Vec<Diff>,
+}
+
+/// The result of `DeepDiff::compare_capped`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CappedDiffs {
+    pub diffs: Vec<Diff>,
+    /// Whether the comparison stopped at a limit before finding every diff.
+    pub truncated: bool,
+}
+
+/// Measurements of one comparison, passed to the `on_metrics` callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffMetrics {
+    /// Pairs of nodes visited. Identical subtrees count as one node.
+    pub nodes: usize,
+    pub elapsed: Duration,
+    pub diffs: usize,
+}
+
+type MetricsCallback = dyn Fn(&DiffMetrics) + Send + Sync;
+
+const SUMMARY_SAMPLE_SIZE: usize = 10;
+
+const TRUNCATED_MARKER: &str = "<truncated>";
+
+impl DiffSummary {
+    fn from_diffs(diffs: Vec<Diff>) -> Self {
+        let mut summary = DiffSummary {
+            added: 0,
+            removed: 0,
+            changed: 0,
+            sample: Vec::new(),
+        };
+        for diff in diffs {
+            match &diff {
+                Diff::Added(..) => summary.added += 1,
+                Diff::Removed(..) => summary.removed += 1,
+                Diff::Summarized(_, nested) => {
+                    summary.added += nested.added;
+                    summary.removed += nested.removed;
+                    summary.changed += nested.changed;
+                }
+                _ => summary.changed += 1,
+            }
+            if summary.sample.len() < SUMMARY_SAMPLE_SIZE {
+                summary.sample.push(diff);
+            }
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Value {
+    Null,
     Int(i64),
+    /// An integer above `i64::MAX`. Compares numerically with `Int`.
+    UInt(u64),
+    /// NaN and the infinities, which JSON has no numbers for, serialize to
+    /// human-readable formats as the strings `"NaN"`, `"inf"` and `"-inf"`.
+    #[serde(with = "float_repr")]
     Float(f64),
     String(String),
     Bool(bool),
@@ -24,7 +111,9 @@ pub enum Value {
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
+            (Value::Null, Value::Null) => true,
             (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(_) | Value::UInt(_), Value::Int(_) | Value::UInt(_)) => self.as_i128() == other.as_i128(),
             (Value::Float(a), Value::Float(b)) => {
                 if a.is_nan() && b.is_nan() {
                     true
@@ -46,10 +135,19 @@ impl Eq for Value {}
 impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match self {
+            Value::Null => state.write_u8(0),
             Value::Int(i) => {
                 state.write_u8(1);
                 i.hash(state);
             }
+            // Hashes like the equal `Int` when there is one.
+            Value::UInt(u) => match i64::try_from(*u) {
+                Ok(i) => Value::Int(i).hash(state),
+                Err(_) => {
+                    state.write_u8(7);
+                    u.hash(state);
+                }
+            },
             Value::Float(f) => {
                 state.write_u8(2);
                 f.to_bits().hash(state);
@@ -83,7 +181,9 @@ impl PartialOrd for Value {
 impl Ord for Value {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
             (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Int(_) | Value::UInt(_), Value::Int(_) | Value::UInt(_)) => self.as_i128().cmp(&other.as_i128()),
             (Value::Float(a), Value::Float(b)) => {
                 a.partial_cmp(b).unwrap_or(Ordering::Equal)
             }
@@ -96,10 +196,71 @@ impl Ord for Value {
     }
 }
 
+impl From<&JsonValue> for Value {
+    fn from(json: &JsonValue) -> Self {
+        match json {
+            JsonValue::Null => Value::Null,
+            JsonValue::Bool(b) => Value::Bool(*b),
+            JsonValue::Number(n) => number_to_value(n),
+            JsonValue::String(s) => Value::String(s.clone()),
+            JsonValue::Array(arr) => Value::Array(arr.iter().map(Value::from).collect()),
+            JsonValue::Object(obj) => Value::Dict(obj.iter().map(|(k, v)| (k.clone(), Value::from(v))).collect()),
+        }
+    }
+}
+
+impl From<JsonValue> for Value {
+    fn from(json: JsonValue) -> Self {
+        match json {
+            JsonValue::Array(arr) => Value::Array(arr.into_iter().map(Value::from).collect()),
+            JsonValue::Object(obj) => Value::Dict(obj.into_iter().map(|(k, v)| (k, Value::from(v))).collect()),
+            JsonValue::String(s) => Value::String(s),
+            scalar => Value::from(&scalar),
+        }
+    }
+}
+
+impl From<&Value> for JsonValue {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => JsonValue::Null,
+            Value::Int(i) => JsonValue::from(*i),
+            Value::UInt(u) => JsonValue::from(*u),
+            Value::Float(f) => JsonValue::from(*f),
+            Value::String(s) => JsonValue::String(s.clone()),
+            Value::Bool(b) => JsonValue::Bool(*b),
+            Value::Array(arr) => JsonValue::Array(arr.iter().map(JsonValue::from).collect()),
+            Value::Dict(dict) => JsonValue::Object(dict.iter().map(|(k, v)| (k.clone(), JsonValue::from(v))).collect()),
+        }
+    }
+}
+
+impl From<Value> for JsonValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Array(arr) => JsonValue::Array(arr.into_iter().map(JsonValue::from).collect()),
+            Value::Dict(dict) => JsonValue::Object(dict.into_iter().map(|(k, v)| (k, JsonValue::from(v))).collect()),
+            Value::String(s) => JsonValue::String(s),
+            scalar => JsonValue::from(&scalar),
+        }
+    }
+}
+
+fn number_to_value(n: &serde_json::Number) -> Value {
+    if let Some(i) = n.as_i64() {
+        Value::Int(i)
+    } else if let Some(u) = n.as_u64() {
+        Value::UInt(u)
+    } else {
+        Value::Float(n.as_f64().unwrap())
+    }
+}
+
 impl Value {
     fn variant_order(&self) -> u8 {
         match self {
-            Value::Int(_) => 1,
+            Value::Null => 0,
+            Value::Int(_) | Value::UInt(_) => 1,
             Value::Float(_) => 2,
             Value::String(_) => 3,
             Value::Bool(_) => 4,
@@ -107,20 +268,367 @@ impl Value {
             Value::Dict(_) => 6,
         }
     }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::UInt(u) => Some(*u as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Int(i) => Some(*i as i128),
+            Value::UInt(u) => Some(*u as i128),
+            _ => None,
+        }
+    }
+
+    pub fn get_path(&self, path: &str) -> Result<&Value, PathError> {
+        let mut current = self;
+        for segment in parse_path(path) {
+            let next = match (current, &segment) {
+                (Value::Dict(dict), PathSegment::Key(key)) => dict.get(key),
+                (Value::Array(arr), PathSegment::Index(index)) => arr.get(*index),
+                (Value::Array(arr), PathSegment::Keyed(field, value)) => {
+                    keyed_position(arr, field, value).map(|index| &arr[index])
+                }
+                _ => None,
+            };
+            current = next.ok_or_else(|| PathError::NotFound(path.to_string()))?;
+        }
+        Ok(current)
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, Value::Int(_) | Value::UInt(_) | Value::Float(_))
+    }
+
+    fn is_collection(&self) -> bool {
+        matches!(self, Value::Array(_) | Value::Dict(_))
+    }
+
+    /// A hash of the value's content that is stable across runs.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The JSON type of this value: `null`, `int`, `float`, `string`, `bool`,
+    /// `array` or `object`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Int(_) | Value::UInt(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Array(_) => "array",
+            Value::Dict(_) => "object",
+        }
+    }
+
+    fn as_text(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Value::Int(i) => Some(Cow::Owned(i.to_string())),
+            Value::UInt(u) => Some(Cow::Owned(u.to_string())),
+            Value::Float(f) => Some(Cow::Owned(f.to_string())),
+            Value::String(s) => Some(Cow::Borrowed(s)),
+            Value::Bool(b) => Some(Cow::Owned(b.to_string())),
+            Value::Null | Value::Array(_) | Value::Dict(_) => None,
+        }
+    }
+
+    fn is_empty_collection(&self) -> bool {
+        match self {
+            Value::Array(arr) => arr.is_empty(),
+            Value::Dict(dict) => dict.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+impl Diff {
+    pub fn path(&self) -> &str {
+        match self {
+            Diff::Added(path, _)
+            | Diff::Removed(path, _)
+            | Diff::Changed(path, _, _)
+            | Diff::StructureChanged(path, _, _)
+            | Diff::TypeChanged(path, _, _)
+            | Diff::NumericChanged(path, _, _, _)
+            | Diff::Summarized(path, _)
+            | Diff::Reordered(path) => path,
+        }
+    }
+
+    pub(crate) fn path_mut(&mut self) -> &mut String {
+        match self {
+            Diff::Added(path, _)
+            | Diff::Removed(path, _)
+            | Diff::Changed(path, _, _)
+            | Diff::StructureChanged(path, _, _)
+            | Diff::TypeChanged(path, _, _)
+            | Diff::NumericChanged(path, _, _, _)
+            | Diff::Summarized(path, _)
+            | Diff::Reordered(path) => path,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Diff::Added(..) => "added",
+            Diff::Removed(..) => "removed",
+            Diff::Changed(..) => "changed",
+            Diff::StructureChanged(..) => "structure_changed",
+            Diff::TypeChanged(..) => "type_changed",
+            Diff::NumericChanged(..) => "numeric_changed",
+            Diff::Summarized(..) => "summarized",
+            Diff::Reordered(..) => "reordered",
+        }
+    }
+
+    pub fn inverted(&self) -> Diff {
+        match self {
+            Diff::Added(path, value) => Diff::Removed(path.clone(), value.clone()),
+            Diff::Removed(path, value) => Diff::Added(path.clone(), value.clone()),
+            Diff::Changed(path, old, new) => Diff::Changed(path.clone(), new.clone(), old.clone()),
+            Diff::StructureChanged(path, old, new) => {
+                Diff::StructureChanged(path.clone(), new.clone(), old.clone())
+            }
+            Diff::TypeChanged(path, old, new) => Diff::TypeChanged(path.clone(), new.clone(), old.clone()),
+            Diff::NumericChanged(path, old, new, _) => {
+                Diff::NumericChanged(path.clone(), new.clone(), old.clone(), percent_change(new, old))
+            }
+            Diff::Summarized(path, summary) => Diff::Summarized(
+                path.clone(),
+                DiffSummary {
+                    added: summary.removed,
+                    removed: summary.added,
+                    changed: summary.changed,
+                    sample: invert(&summary.sample),
+                },
+            ),
+            Diff::Reordered(path) => Diff::Reordered(path.clone()),
+        }
+    }
+
+    fn map_values(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        match self {
+            Diff::Added(_, value) | Diff::Removed(_, value) => f(value),
+            Diff::Changed(_, old, new)
+            | Diff::StructureChanged(_, old, new)
+            | Diff::TypeChanged(_, old, new)
+            | Diff::NumericChanged(_, old, new, _) => {
+                f(old);
+                f(new);
+            }
+            Diff::Summarized(_, summary) => {
+                for diff in &mut summary.sample {
+                    diff.map_values(f);
+                }
+            }
+            Diff::Reordered(_) => {}
+        }
+    }
+
+    /// `to_log_line` with floats rounded to `decimals` places.
+    pub fn to_log_line_with_precision(&self, decimals: u32) -> String {
+        self.rounded(decimals).to_log_line()
+    }
+
+    pub(crate) fn rounded(&self, decimals: u32) -> Diff {
+        let mut diff = self.clone();
+        diff.map_values(&mut |value| round_floats(value, decimals));
+        diff
+    }
+
+    pub fn to_log_line(&self) -> String {
+        match self {
+            Diff::Added(path, new) => format!("{}: (none) => {}", path, canonical_json(new)),
+            Diff::Removed(path, old) => format!("{}: {} => (none)", path, canonical_json(old)),
+            Diff::Changed(path, old, new)
+            | Diff::StructureChanged(path, old, new)
+            | Diff::TypeChanged(path, old, new) => {
+                format!("{}: {} => {}", path, canonical_json(old), canonical_json(new))
+            }
+            Diff::NumericChanged(path, old, new, percent) => match percent {
+                Some(percent) => format!(
+                    "{}: {} => {} ({:+}%)",
+                    path,
+                    canonical_json(old),
+                    canonical_json(new),
+                    percent
+                ),
+                None => format!("{}: {} => {}", path, canonical_json(old), canonical_json(new)),
+            },
+            Diff::Summarized(path, summary) => {
+                let mut line = format!(
+                    "{}: {} added, {} removed, {} changed",
+                    path, summary.added, summary.removed, summary.changed
+                );
+                if !summary.sample.is_empty() {
+                    let sample: Vec<String> = summary.sample.iter().map(Diff::to_log_line).collect();
+                    line.push_str(&format!(" (sample: {})", sample.join("; ")));
+                }
+                line
+            }
+            Diff::Reordered(path) => format!("{}: reordered", path),
+        }
+    }
+}
+
+/// How ordered arrays are lined up before their elements are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayAlgo {
+    /// Element `i` is compared with element `i`.
+    Positional,
+    /// Elements are aligned by longest common subsequence, so an insertion
+    /// or deletion is reported once instead of shifting every later index.
+    /// Among equal elements the earliest are kept, so `[a, a, b]` against
+    /// `[a, b]` removes the second `a`.
+    Lcs,
+}
+
+/// How diff paths are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathFormat {
+    /// `a.b[0]`, with awkward keys quoted as `a["b.c"]`.
+    Dotted,
+    /// An RFC 6901 JSON Pointer, `/a/b/0`. The helpers that parse paths,
+    /// such as `to_json_patch` and `Value::get_path`, expect `Dotted`.
+    JsonPointer,
 }
 
+type ArrayIdentity = dyn Fn(&Value) -> Value + Send + Sync;
+
+type KeyNormalizer = dyn Fn(&str) -> String + Send + Sync;
+
+pub(crate) type DictEntry<'a> = (&'a str, Option<&'a Value>, Option<&'a Value>);
+
 pub struct DeepDiff {
     ignore_order: bool,
+    order_by_depth: Vec<bool>,
     float_tolerance: Option<f64>,
     use_percent: bool,
+    bool_string_equivalence: bool,
+    value_aliases: Vec<Vec<Value>>,
+    array_identity: Option<Arc<ArrayIdentity>>,
+    rollup_subtrees: bool,
+    top_n: Option<(usize, Arc<DiffScore>)>,
+    max_output_bytes: Option<usize>,
+    max_diffs: Option<usize>,
+    float_output_precision: Option<u32>,
+    top_level_keys: Option<BTreeSet<String>>,
+    ignored_keys: BTreeSet<String>,
+    only_paths: Vec<PathPattern>,
+    excluded_paths: Vec<PathPattern>,
+    annotate_numeric_delta: bool,
+    case_insensitive_keys: bool,
+    key_normalizer: Option<Arc<KeyNormalizer>>,
+    case_insensitive_strings: bool,
+    nan_not_equal: bool,
+    numeric_strings: bool,
+    preserve_zero_padding: bool,
+    canonical_order_key: Option<String>,
+    discriminator_field: Option<String>,
+    array_key: Option<String>,
+    defaults: BTreeMap<String, Value>,
+    unset_sentinels: Vec<Value>,
+    unwrap_singleton_arrays: bool,
+    numeric_coercion: bool,
+    max_depth: Option<usize>,
+    dict_summary_threshold: Option<usize>,
+    ignore_added_empty: bool,
+    datetime_aware: bool,
+    datetime_tolerance: Duration,
+    ignore_line_ending_differences: bool,
+    unordered_source_indices: bool,
+    string_fields: Vec<PathPattern>,
+    ignored_array_values: Vec<Value>,
+    float_sig_figs: Option<u32>,
+    value_depth_limit: Option<usize>,
+    unwrap_path: Option<String>,
+    cache: Option<Mutex<DiffCache>>,
+    array_structure_only: bool,
+    numeric_string_fields: Vec<PathPattern>,
+    array_algorithm: ArrayAlgo,
+    tolerated_edge_edits: (usize, usize),
+    path_format: PathFormat,
+    separator: String,
+    report_reorders: bool,
+    breaking_rules: BreakingRules,
+    on_metrics: Option<Arc<MetricsCallback>>,
+    #[cfg(feature = "parallel")]
+    parallel: bool,
+}
+
+impl Default for DeepDiff {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DeepDiff {
     pub fn new() -> Self {
         DeepDiff {
             ignore_order: false,
+            order_by_depth: Vec::new(),
             float_tolerance: None,
             use_percent: false,
+            bool_string_equivalence: false,
+            value_aliases: Vec::new(),
+            array_identity: None,
+            rollup_subtrees: false,
+            top_n: None,
+            max_output_bytes: None,
+            max_diffs: None,
+            float_output_precision: None,
+            top_level_keys: None,
+            ignored_keys: BTreeSet::new(),
+            only_paths: Vec::new(),
+            excluded_paths: Vec::new(),
+            annotate_numeric_delta: false,
+            case_insensitive_keys: false,
+            key_normalizer: None,
+            case_insensitive_strings: false,
+            nan_not_equal: false,
+            numeric_strings: false,
+            preserve_zero_padding: false,
+            canonical_order_key: None,
+            discriminator_field: None,
+            array_key: None,
+            defaults: BTreeMap::new(),
+            unset_sentinels: Vec::new(),
+            unwrap_singleton_arrays: false,
+            numeric_coercion: false,
+            max_depth: None,
+            dict_summary_threshold: None,
+            ignore_added_empty: false,
+            datetime_aware: false,
+            datetime_tolerance: Duration::ZERO,
+            ignore_line_ending_differences: false,
+            unordered_source_indices: true,
+            string_fields: Vec::new(),
+            ignored_array_values: Vec::new(),
+            float_sig_figs: None,
+            value_depth_limit: None,
+            unwrap_path: None,
+            cache: None,
+            array_structure_only: false,
+            numeric_string_fields: Vec::new(),
+            array_algorithm: ArrayAlgo::Positional,
+            tolerated_edge_edits: (0, 0),
+            path_format: PathFormat::Dotted,
+            separator: ".".to_string(),
+            report_reorders: false,
+            breaking_rules: BreakingRules::default(),
+            on_metrics: None,
+            #[cfg(feature = "parallel")]
+            parallel: false,
         }
     }
 
@@ -129,164 +637,2052 @@ impl DeepDiff {
         self
     }
 
+    /// Chooses `ignore_order` per array by how many arrays enclose it:
+    /// `settings[0]` applies to outermost arrays, `settings[1]` to arrays
+    /// directly or indirectly inside one, and so on. Deeper arrays use
+    /// `ignore_order`.
+    pub fn order_settings_per_depth(mut self, settings: Vec<bool>) -> Self {
+        self.order_by_depth = settings;
+        self
+    }
+
     pub fn float_tolerance(mut self, value: f64, use_percent: bool) -> Self {
         self.float_tolerance = Some(value);
         self.use_percent = use_percent;
         self
     }
 
-    pub fn compare(&self, v1: &Value, v2: &Value) -> Vec<Diff> {
-        self.compare_recursive(v1, v2, String::new())
+    pub fn bool_string_equivalence(mut self, value: bool) -> Self {
+        self.bool_string_equivalence = value;
+        self
     }
 
-    fn compare_recursive(&self, v1: &Value, v2: &Value, path: String) -> Vec<Diff> {
-        match (v1, v2) {
-            (Value::Dict(dict1), Value::Dict(dict2)) => self.compare_dicts(dict1, dict2, path),
-            (Value::Array(arr1), Value::Array(arr2)) => self.compare_arrays(arr1, arr2, path),
-            _ => {
-                if self.values_equal(v1, v2) {
-                    vec![]
-                } else {
-                    vec![Diff::Changed(path, v1.clone(), v2.clone())]
-                }
-            }
-        }
+    /// Compares an integer against a float by value, so `5` equals `5.0`.
+    /// Float options such as `float_tolerance` apply.
+    pub fn numeric_coercion(mut self, value: bool) -> Self {
+        self.numeric_coercion = value;
+        self
     }
 
-    fn compare_dicts(
-        &self,
-        dict1: &BTreeMap<String, Value>,
-        dict2: &BTreeMap<String, Value>,
-        path: String,
-    ) -> Vec<Diff> {
-        let mut diffs = Vec::new();
+    pub fn value_alias_group(mut self, values: Vec<Value>) -> Self {
+        self.value_aliases.push(values);
+        self
+    }
 
-        for (key, value1) in dict1 {
-            let new_path = if path.is_empty() {
-                key.clone()
-            } else {
-                format!("{}.{}", path, key)
-            };
-            match dict2.get(key) {
-                Some(value2) => {
-                    diffs.extend(self.compare_recursive(value1, value2, new_path));
-                }
-                None => diffs.push(Diff::Removed(new_path, value1.clone())),
-            }
-        }
+    pub fn array_identity(mut self, identity: Arc<ArrayIdentity>) -> Self {
+        self.array_identity = Some(identity);
+        self
+    }
 
-        for (key, value2) in dict2 {
-            if !dict1.contains_key(key) {
-                let new_path = if path.is_empty() {
-                    key.clone()
-                } else {
-                    format!("{}.{}", path, key)
-                };
-                diffs.push(Diff::Added(new_path, value2.clone()));
-            }
-        }
+    /// Reports a subtree that shares nothing with its counterpart as a single
+    /// `Changed`, anchored at the deepest node that still contains every
+    /// difference, instead of one diff per leaf.
+    pub fn rollup_subtrees(mut self, value: bool) -> Self {
+        self.rollup_subtrees = value;
+        self
+    }
 
-        diffs
+    pub fn top_n_by(mut self, n: usize, score: Arc<DiffScore>) -> Self {
+        self.top_n = Some((n, score));
+        self
     }
 
-    fn compare_arrays(&self, arr1: &[Value], arr2: &[Value], path: String) -> Vec<Diff> {
-        if self.ignore_order {
-            self.compare_arrays_unordered(arr1, arr2, path)
-        } else {
-            self.compare_arrays_ordered(arr1, arr2, path)
-        }
+    /// Stops collecting once the diffs would serialize to more than `n`
+    /// bytes of JSON, as from `diffs_to_json`. `compare_capped` reports
+    /// whether any were left out. Doesn't apply with `top_n_by`, whose
+    /// output is already bounded.
+    pub fn max_output_bytes(mut self, n: usize) -> Self {
+        self.max_output_bytes = Some(n);
+        self
     }
 
-    fn compare_arrays_ordered(
-        &self,
-        arr1: &[Value],
-        arr2: &[Value],
-        path: String,
-    ) -> Vec<Diff> {
-        let mut diffs = Vec::new();
-        let max_len = arr1.len().max(arr2.len());
+    /// Stops the comparison once `n` diffs are found. `compare_capped`
+    /// reports whether any were left out. Doesn't apply with `top_n_by`.
+    pub fn max_diffs(mut self, n: usize) -> Self {
+        self.max_diffs = Some(n);
+        self
+    }
 
-        for i in 0..max_len {
-            let new_path = if self.ignore_order { path.clone() } else { format!("{}[{}]", path, i) };
-            match (arr1.get(i), arr2.get(i)) {
-                (Some(v1), Some(v2)) => {
-                    diffs.extend(self.compare_recursive(v1, v2, new_path));
-                }
-                (Some(v1), None) => diffs.push(Diff::Removed(new_path, v1.clone())),
-                (None, Some(v2)) => diffs.push(Diff::Added(new_path, v2.clone())),
-                (None, None) => unreachable!(),
-            }
-        }
+    /// Rounds floats to `decimals` places when diffs are serialized through
+    /// `DeepDiff::diffs_to_json`, `DeepDiff::json_options` or `compare_full`.
+    /// The diffs themselves keep the exact values.
+    pub fn float_output_precision(mut self, decimals: u32) -> Self {
+        self.float_output_precision = Some(decimals);
+        self
+    }
 
-        diffs
+    pub fn top_level_keys(mut self, keys: &[&str]) -> Self {
+        self.top_level_keys = Some(keys.iter().map(|key| key.to_string()).collect());
+        self
     }
 
-    fn compare_arrays_unordered(
-        &self,
-        arr1: &[Value],
-        arr2: &[Value],
-        path: String,
-    ) -> Vec<Diff> {
-        let mut sorted1 = arr1.to_vec();
-        let mut sorted2 = arr2.to_vec();
+    /// Skips dict entries with any of these keys, at any depth.
+    pub fn ignore_keys(mut self, keys: Vec<String>) -> Self {
+        self.ignored_keys = keys.into_iter().collect();
+        self
+    }
 
-        sorted1.sort();
-        sorted2.sort();
+    /// Reports only diffs at or below paths matching one of `patterns`,
+    /// skipping subtrees that can't lead to a match. Diffs above a match,
+    /// such as a removed parent, are dropped. Patterns take the wildcards
+    /// `string_compare_field` does.
+    pub fn only_paths(mut self, patterns: Vec<String>) -> Self {
+        self.only_paths = patterns.iter().map(|pattern| PathPattern::new(pattern)).collect();
+        self
+    }
 
-        self.compare_arrays_ordered(&sorted1, &sorted2, path)
+    /// Drops diffs at or below paths matching one of `patterns`. Takes
+    /// precedence over `only_paths`.
+    pub fn exclude_paths(mut self, patterns: Vec<String>) -> Self {
+        self.excluded_paths = patterns.iter().map(|pattern| PathPattern::new(pattern)).collect();
+        self
     }
 
-    fn values_equal(&self, v1: &Value, v2: &Value) -> bool {
-        match (v1, v2) {
-            (Value::Float(f1), Value::Float(f2)) => {
-                if let Some(tolerance) = self.float_tolerance {
-                    let diff = (f1 - f2).abs();
-                    if self.use_percent {
-                        let max = f1.abs().max(f2.abs());
-                        diff / max <= tolerance
-                    } else {
-                        diff <= tolerance
-                    }
-                } else {
-                    f1 == f2
-                }
-            }
-            _ => v1 == v2,
-        }
+    pub fn annotate_numeric_delta(mut self, value: bool) -> Self {
+        self.annotate_numeric_delta = value;
+        self
     }
 
-    pub fn compare_json(&self, json1: &JsonValue, json2: &JsonValue) -> Vec<Diff> {
-        let v1 = self.json_to_value(json1);
-        let v2 = self.json_to_value(json2);
-        self.compare(&v1, &v2)
+    pub fn case_insensitive_keys(mut self, value: bool) -> Self {
+        self.case_insensitive_keys = value;
+        self
     }
 
-    fn json_to_value(&self, json: &JsonValue) -> Value {
-        match json {
-            JsonValue::Null => Value::String("null".to_string()),
-            JsonValue::Bool(b) => Value::Bool(*b),
-            JsonValue::Number(n) => {
-                if n.is_i64() {
-                    Value::Int(n.as_i64().unwrap())
-                } else {
-                    Value::Float(n.as_f64().unwrap())
-                }
-            }
-            JsonValue::String(s) => Value::String(s.clone()),
-            JsonValue::Array(arr) => {
-                Value::Array(arr.iter().map(|v| self.json_to_value(v)).collect())
-            }
-            JsonValue::Object(obj) => {
-                let mut map = BTreeMap::new();
-                for (k, v) in obj {
-                    map.insert(k.clone(), self.json_to_value(v));
-                }
-                Value::Dict(map)
-            }
-        }
+    /// Matches dict keys by `normalizer(key)`, e.g. to pair `user_name` with
+    /// `userName`. Paths use the key from `v1` where both sides have one.
+    /// When several keys of one dict normalize alike, the first in key order
+    /// is matched and the rest are reported as removed or added. Applied
+    /// before `case_insensitive_keys` folds case.
+    pub fn normalize_key(mut self, normalizer: Arc<KeyNormalizer>) -> Self {
+        self.key_normalizer = Some(normalizer);
+        self
     }
-}
 
-pub fn diffs_to_json(diffs: &[Diff]) -> JsonValue {
-    serde_json::to_value(diffs).unwrap()
-}
\ No newline at end of file
+    /// String values compare by their `to_lowercase` form. Keys are
+    /// unaffected. `case_insensitive_values` is an alias for this setting.
+    pub fn case_insensitive_strings(mut self, value: bool) -> Self {
+        self.case_insensitive_strings = value;
+        self
+    }
+
+    /// Alias for `case_insensitive_strings`; both set the same option, so
+    /// whichever is called last wins.
+    pub fn case_insensitive_values(self, value: bool) -> Self {
+        self.case_insensitive_strings(value)
+    }
+
+    /// Shorthand for `case_insensitive_keys(value).case_insensitive_strings(value)`.
+    pub fn case_insensitive(self, value: bool) -> Self {
+        self.case_insensitive_keys(value).case_insensitive_strings(value)
+    }
+
+    pub fn nan_not_equal(mut self, value: bool) -> Self {
+        self.nan_not_equal = value;
+        self
+    }
+
+    /// Compares strings that parse as numbers by their numeric value, against
+    /// each other and against `Int`/`Float`, so `"007"`, `"7"` and `7` are all
+    /// equal. Beware of id-like fields such as zip codes or account numbers,
+    /// where leading zeros are significant; see `preserve_zero_padding`.
+    pub fn numeric_strings(mut self, value: bool) -> Self {
+        self.numeric_strings = value;
+        self
+    }
+
+    /// Under `numeric_strings`, keeps comparing zero-padded strings such as
+    /// `"007"` as plain text instead of as numbers.
+    pub fn preserve_zero_padding(mut self, value: bool) -> Self {
+        self.preserve_zero_padding = value;
+        self
+    }
+
+    /// Sorts both sides of every array of records by the `key` field before an
+    /// ordered comparison, so reordered records line up. Paths index into the
+    /// sorted arrays, not the inputs.
+    pub fn canonicalize_array_order(mut self, key: &str) -> Self {
+        self.canonical_order_key = Some(key.to_string());
+        self
+    }
+
+    /// Pairs array elements only with elements whose `field` holds the same
+    /// value, in order, so records of different kinds in one array are never
+    /// compared with each other. Combines with `array_identity`.
+    pub fn discriminator_field(mut self, field: &str) -> Self {
+        self.discriminator_field = Some(field.to_string());
+        self
+    }
+
+    /// Pairs array elements of objects by the value of `field`, and writes
+    /// their paths with it, e.g. `users[id=2].name`. String values are
+    /// quoted, as in `[id="a"]`. Elements without the field keep their index.
+    /// Like `PathFormat::JsonPointer`, the helpers that parse paths don't
+    /// understand these segments.
+    pub fn array_key(mut self, field: &str) -> Self {
+        self.array_key = Some(field.to_string());
+        self
+    }
+
+    /// Default values by key path, e.g. `settings.theme`. A key missing on
+    /// one side matches the other side holding its default.
+    pub fn defaults(mut self, defaults: BTreeMap<String, Value>) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Values meaning "not set", such as `-1` or `""`. A sentinel matches a
+    /// missing key or any other sentinel.
+    pub fn unset_sentinels(mut self, values: Vec<Value>) -> Self {
+        self.unset_sentinels = values;
+        self
+    }
+
+    /// Compares a one-element array against a non-array on the other side
+    /// as its element, so `["x"]` matches `"x"`.
+    pub fn unwrap_singleton_arrays(mut self, value: bool) -> Self {
+        self.unwrap_singleton_arrays = value;
+        self
+    }
+
+    /// Stops descending below `depth` path segments: two differing objects
+    /// or arrays at that depth are reported as one `Diff::Changed` holding
+    /// both subtrees. Depth 0 compares only the roots. This bounds the
+    /// output, not the recursion: telling whether two subtrees at the limit
+    /// differ still reads them in full.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Collapses the diffs under any dict with more than `n` keys into one
+    /// `Diff::Summarized` holding counts and a small sample. Summaries are
+    /// lossy, so update payloads and structured reports skip them.
+    pub fn dict_summary_threshold(mut self, n: usize) -> Self {
+        self.dict_summary_threshold = Some(n);
+        self
+    }
+
+    pub fn ignore_added_empty(mut self, value: bool) -> Self {
+        self.ignore_added_empty = value;
+        self
+    }
+
+    /// Compares strings that both parse as RFC 3339 timestamps by the instant
+    /// they denote, so differing offsets or precision don't count as changes.
+    /// Anything else falls back to plain string comparison.
+    pub fn datetime_aware(mut self, value: bool) -> Self {
+        self.datetime_aware = value;
+        self
+    }
+
+    /// Treats timestamps at most `tolerance` apart as equal. Implies
+    /// `datetime_aware`.
+    pub fn datetime_tolerance(mut self, tolerance: Duration) -> Self {
+        self.datetime_aware = true;
+        self.datetime_tolerance = tolerance;
+        self
+    }
+
+    /// Compares strings with `\r\n` and `\r` read as `\n` and trailing
+    /// newlines dropped. Reported values keep their original text.
+    pub fn ignore_line_ending_differences(mut self, value: bool) -> Self {
+        self.ignore_line_ending_differences = value;
+        self
+    }
+
+    /// Under `ignore_order`, suffixes each element diff with the index the
+    /// element had in its source array: `v1` for removals and matches, `v2`
+    /// for additions. On by default; turning it off reports every element
+    /// diff at the array's own path.
+    pub fn unordered_source_indices(mut self, value: bool) -> Self {
+        self.unordered_source_indices = value;
+        self
+    }
+
+    /// Compares scalars at paths matching `path_pattern` by their text, and
+    /// makes `compare_json` read numbers there as strings so large ids keep
+    /// every digit. Patterns accept `*`/`[*]` for one segment and `**` for
+    /// any number of segments.
+    pub fn string_compare_field(mut self, path_pattern: &str) -> Self {
+        self.string_fields.push(PathPattern::new(path_pattern));
+        self
+    }
+
+    /// Under `ignore_order`, drops additions and removals of these placeholder
+    /// elements.
+    pub fn ignore_array_values(mut self, values: Vec<Value>) -> Self {
+        self.ignored_array_values = values;
+        self
+    }
+
+    /// Treats floats as equal when they round to the same `n` significant
+    /// figures. Takes precedence over `float_tolerance`.
+    pub fn float_sig_figs(mut self, n: u32) -> Self {
+        self.float_sig_figs = Some(n);
+        self
+    }
+
+    /// Keeps at most `depth` levels of nesting in the values reported by each
+    /// diff, replacing deeper collections with a `"<truncated>"` string.
+    /// Comparison still sees the full values.
+    pub fn value_depth_limit(mut self, depth: usize) -> Self {
+        self.value_depth_limit = Some(depth);
+        self
+    }
+
+    /// Compares only the payloads found at `path` in both documents, reporting
+    /// paths relative to the payload. Documents missing the path on either
+    /// side are compared whole.
+    pub fn unwrap_path(mut self, path: &str) -> Self {
+        self.unwrap_path = Some(path.to_string());
+        self
+    }
+
+    /// Ignores value changes between matched scalar array elements of the
+    /// same type; additions, removals and type changes are still reported.
+    pub fn array_structure_only(mut self, value: bool) -> Self {
+        self.array_structure_only = value;
+        self
+    }
+
+    /// Memoizes `compare` results by the content hashes of both inputs,
+    /// keeping the most recently used results.
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(Mutex::new(DiffCache::new(DEFAULT_CACHE_SIZE)));
+        self
+    }
+
+    /// Enables the result cache with room for `n` comparisons.
+    pub fn cache_size(mut self, n: usize) -> Self {
+        self.cache = Some(Mutex::new(DiffCache::new(n)));
+        self
+    }
+
+    /// Calls `callback` after each comparison that walks the documents;
+    /// results served from the cache aren't reported.
+    pub fn on_metrics(mut self, callback: Arc<MetricsCallback>) -> Self {
+        self.on_metrics = Some(callback);
+        self
+    }
+
+    /// Compares the entries of large dicts on the rayon thread pool. Diffs
+    /// come out in the same order as a sequential run.
+    #[cfg(feature = "parallel")]
+    pub fn parallel(mut self, value: bool) -> Self {
+        self.parallel = value;
+        self
+    }
+
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().clear();
+        }
+    }
+
+    /// Applies `numeric_strings` only at paths matching `path_pattern`, so
+    /// other fields such as ids stay strict.
+    pub fn numeric_string_at(mut self, path_pattern: &str) -> Self {
+        self.numeric_string_fields.push(PathPattern::new(path_pattern));
+        self
+    }
+
+    pub fn array_algorithm(mut self, algorithm: ArrayAlgo) -> Self {
+        self.array_algorithm = algorithm;
+        self
+    }
+
+    /// Under `ArrayAlgo::Lcs`, ignores a run of up to `leading` insertions or
+    /// deletions before the first unchanged element and up to `trailing`
+    /// after the last one, as when a rolling log drops old entries and
+    /// appends new ones. Longer runs are reported in full.
+    pub fn tolerate_edge_edits(mut self, leading: usize, trailing: usize) -> Self {
+        self.tolerated_edge_edits = (leading, trailing);
+        self
+    }
+
+    /// Under `ignore_order`, reports an array holding the same elements in a
+    /// different order as `Diff::Reordered` rather than as no change.
+    pub fn report_reorders(mut self, value: bool) -> Self {
+        self.report_reorders = value;
+        self
+    }
+
+    pub fn path_format(mut self, format: PathFormat) -> Self {
+        self.path_format = format;
+        self
+    }
+
+    /// Joins dict keys in `Dotted` paths with `separator` instead of `.`;
+    /// array indices stay `[i]`. Like `PathFormat::JsonPointer`, the result
+    /// isn't understood by the helpers that parse paths.
+    ///
+    /// Panics if `separator` is empty.
+    pub fn separator(mut self, separator: String) -> Self {
+        assert!(!separator.is_empty(), "path separator must not be empty");
+        self.separator = separator;
+        self
+    }
+
+    pub fn breaking_change_rules(mut self, rules: BreakingRules) -> Self {
+        self.breaking_rules = rules;
+        self
+    }
+
+    pub(crate) fn breaking_rules(&self) -> BreakingRules {
+        self.breaking_rules
+    }
+
+    pub fn compare(&self, v1: &Value, v2: &Value) -> Vec<Diff> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.compare_uncached(v1, v2),
+        };
+
+        let key = (v1.content_hash(), v2.content_hash());
+        if let Some(diffs) = cache.lock().unwrap().get(key) {
+            return diffs;
+        }
+        let diffs = self.compare_uncached(v1, v2);
+        cache.lock().unwrap().insert(key, diffs.clone());
+        diffs
+    }
+
+    fn compare_uncached(&self, v1: &Value, v2: &Value) -> Vec<Diff> {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        self.compare_from(v1, v2, String::new(), |_| true, None).0
+    }
+
+    /// Whether `v1` and `v2` differ under the configured options, stopping
+    /// at the first difference.
+    pub fn has_changes(&self, v1: &Value, v2: &Value) -> bool {
+        self.first_diff(v1, v2).is_some()
+    }
+
+    /// Returns the earliest diff in traversal order, stopping as soon as it
+    /// is found.
+    pub fn first_diff(&self, v1: &Value, v2: &Value) -> Option<Diff> {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        let mut first = First::default();
+        self.compare_recursive(v1, v2, String::new(), &mut first);
+        first.into_inner()
+    }
+
+    /// Like `compare`, but keeps only the diffs `predicate` accepts, dropping
+    /// the rest as they are found instead of collecting them first.
+    pub fn compare_filtered<F: Fn(&Diff) -> bool>(&self, v1: &Value, v2: &Value, predicate: F) -> Vec<Diff> {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        self.compare_from(v1, v2, String::new(), predicate, None).0
+    }
+
+    /// Like `compare`, also saying whether `max_diffs` or `max_output_bytes`
+    /// cut the diffs short. Bypasses the cache.
+    pub fn compare_capped(&self, v1: &Value, v2: &Value) -> CappedDiffs {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        let (diffs, truncated) = self.compare_from(v1, v2, String::new(), |_| true, None);
+        CappedDiffs { diffs, truncated }
+    }
+
+    /// Sends the diffs of `compare` through `tx` as they are found, blocking
+    /// while the channel is full so a slow receiver paces the traversal.
+    /// Stops early and returns the unsent diff if the receiver hangs up.
+    /// `top_n_by` and the output limits, which need the whole set, don't
+    /// apply.
+    pub fn compare_to_channel(&self, v1: &Value, v2: &Value, tx: SyncSender<Diff>) -> Result<(), SendError<Diff>> {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        let mut channel = Channel::new(tx);
+        self.compare_recursive(v1, v2, String::new(), &mut channel);
+        channel.finish()
+    }
+
+    /// Yields the diffs of `compare` lazily, in the same order, doing only
+    /// the work needed for the diffs consumed. Honours `max_diffs`;
+    /// `top_n_by` and `max_output_bytes`, which need the whole set, don't
+    /// apply.
+    pub fn compare_iter<'a>(&'a self, v1: &'a Value, v2: &'a Value) -> impl Iterator<Item = Diff> + 'a {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        DiffIter::new(self, v1, v2).take(self.max_diffs.unwrap_or(usize::MAX))
+    }
+
+    /// Like `compare`, also returning the paths of numbers that differ but
+    /// were treated as equal, as by `float_tolerance` or `float_sig_figs`.
+    /// Bypasses the cache, which only holds diffs.
+    pub fn compare_with_tolerance_report(&self, v1: &Value, v2: &Value) -> (Vec<Diff>, Vec<String>) {
+        let (v1, v2) = self.unwrapped(v1, v2);
+        let mut tolerated = Vec::new();
+        let (diffs, _) = self.compare_from(v1, v2, String::new(), |_| true, Some(&mut tolerated));
+        (diffs, tolerated)
+    }
+
+    /// `compare` keyed by path. Each path appears once: where an array
+    /// alignment removes one element and adds another at the same index, the
+    /// pair becomes a single change. Paths can otherwise only repeat under
+    /// `ignore_order` with `unordered_source_indices(false)`; there the last
+    /// diff at a path wins.
+    pub fn compare_to_map(&self, v1: &Value, v2: &Value) -> BTreeMap<String, Diff> {
+        let mut map: BTreeMap<String, Diff> = BTreeMap::new();
+        for diff in self.compare(v1, v2) {
+            let path = diff.path().to_string();
+            let diff = match (map.remove(&path), diff) {
+                (Some(Diff::Removed(_, old)), Diff::Added(_, new)) => self.changed(path.clone(), &old, &new),
+                (_, diff) => diff,
+            };
+            map.insert(path, diff);
+        }
+        map
+    }
+
+    fn unwrapped<'v>(&self, v1: &'v Value, v2: &'v Value) -> (&'v Value, &'v Value) {
+        if let Some(envelope) = &self.unwrap_path {
+            if let (Ok(inner1), Ok(inner2)) = (v1.get_path(envelope), v2.get_path(envelope)) {
+                return (inner1, inner2);
+            }
+        }
+        (v1, v2)
+    }
+
+    /// Diffs only the subtrees found at `path` in both documents. Reported
+    /// paths stay rooted at the document root.
+    pub fn compare_at(&self, v1: &Value, v2: &Value, path: &str) -> Result<Vec<Diff>, PathError> {
+        let sub1 = v1.get_path(path)?;
+        let sub2 = v2.get_path(path)?;
+        Ok(self.compare_from(sub1, sub2, path.to_string(), |_| true, None).0)
+    }
+
+    // The diffs found from `path` down, and whether `max_diffs` or
+    // `max_output_bytes` cut them short. Paths of numbers equal only within
+    // tolerance go to `tolerated` if given.
+    fn compare_from<F: Fn(&Diff) -> bool>(
+        &self,
+        v1: &Value,
+        v2: &Value,
+        path: String,
+        predicate: F,
+        tolerated: Option<&mut Vec<String>>,
+    ) -> (Vec<Diff>, bool) {
+        self.collect(&|out| self.compare_recursive(v1, v2, path.clone(), out), predicate, tolerated)
+    }
+
+    // Runs `traverse` into the sink the output options call for, as
+    // `compare_from` does for a plain traversal.
+    pub(crate) fn collect<F: Fn(&Diff) -> bool>(
+        &self,
+        traverse: &dyn Fn(&mut dyn DiffSink),
+        predicate: F,
+        mut tolerated: Option<&mut Vec<String>>,
+    ) -> (Vec<Diff>, bool) {
+        let start = self.on_metrics.as_ref().map(|_| Instant::now());
+        let mut nodes = 0;
+        let bounded = self.max_diffs.is_some() || self.max_output_bytes.is_some();
+        let (diffs, truncated) = match &self.top_n {
+            Some((n, score)) => {
+                let mut top = TopN::new(*n, score.as_ref());
+                self.walk(traverse, &mut Filtered::new(&mut top, predicate), &mut nodes, tolerated.take());
+                (top.into_sorted_vec(), false)
+            }
+            None if bounded => {
+                let mut bounds = Bounded::new(self.max_diffs, self.max_output_bytes);
+                self.walk(traverse, &mut Filtered::new(&mut bounds, predicate), &mut nodes, tolerated.take());
+                bounds.into_inner()
+            }
+            None => {
+                let mut diffs = Vec::new();
+                self.walk(traverse, &mut Filtered::new(&mut diffs, predicate), &mut nodes, tolerated.take());
+                (diffs, false)
+            }
+        };
+
+        if let (Some(callback), Some(start)) = (&self.on_metrics, start) {
+            callback(&DiffMetrics {
+                nodes,
+                elapsed: start.elapsed(),
+                diffs: diffs.len(),
+            });
+        }
+        (diffs, truncated)
+    }
+
+    // Runs the traversal, counting the nodes it visits only when someone
+    // listens for metrics.
+    fn walk(
+        &self,
+        traverse: &dyn Fn(&mut dyn DiffSink),
+        out: &mut dyn DiffSink,
+        nodes: &mut usize,
+        tolerated: Option<&mut Vec<String>>,
+    ) {
+        let mut recorded;
+        let out: &mut dyn DiffSink = match tolerated {
+            Some(tolerated) => {
+                recorded = WithTolerated::new(out, tolerated);
+                &mut recorded
+            }
+            None => out,
+        };
+        if self.on_metrics.is_none() {
+            return traverse(out);
+        }
+        let mut counted = Counted::new(out);
+        traverse(&mut counted);
+        *nodes = counted.nodes;
+    }
+
+    pub fn compare_bidirectional(&self, v1: &Value, v2: &Value) -> (Vec<Diff>, Vec<Diff>) {
+        let forward = self.compare(v1, v2);
+        let reverse = invert(&forward);
+        (forward, reverse)
+    }
+
+    /// Returns what `actual` customizes relative to `defaults`: changed and
+    /// added values. Keys left out of `actual` aren't overrides.
+    pub fn overrides(&self, defaults: &Value, actual: &Value) -> Vec<Diff> {
+        let mut diffs = self.compare(defaults, actual);
+        diffs.retain(|diff| !matches!(diff, Diff::Removed(..)));
+        diffs
+    }
+
+    /// The overrides of `actual` re-nested into a sparse document that holds
+    /// only the customized values.
+    pub fn override_document(&self, defaults: &Value, actual: &Value) -> JsonValue {
+        to_update_payload_with_source(&self.overrides(defaults, actual), actual)
+    }
+
+    pub(crate) fn compare_recursive(&self, v1: &Value, v2: &Value, path: String, out: &mut dyn DiffSink) {
+        if let Some(path) = self.enter_subtree(v1, v2, path, out) {
+            self.compare_values(v1, v2, path, out);
+        }
+    }
+
+    // Settles what `compare_recursive` decides before looking inside the two
+    // values, handing the path back if they still need comparing.
+    pub(crate) fn enter_subtree(&self, v1: &Value, v2: &Value, path: String, out: &mut dyn DiffSink) -> Option<String> {
+        out.enter();
+
+        // Identical subtrees can't produce diffs, and `==` is much cheaper than
+        // walking them while building paths. NaN is the one value `==` treats
+        // as equal that may still be reported.
+        if !self.nan_not_equal && v1 == v2 {
+            return None;
+        }
+
+        if self.has_path_filters() && !out.probing() && !self.may_report_below(&path) {
+            return None;
+        }
+
+        if self.rollup_subtrees && !self.overlaps(v1, v2) {
+            self.rollup(v1, v2, path, out);
+            return None;
+        }
+
+        if let Some(max_depth) = self.max_depth {
+            if v1.is_collection() && v2.is_collection() && path_depth(&path) >= max_depth {
+                let mut probe = First::default();
+                self.compare_values(v1, v2, path.clone(), &mut probe);
+                if probe.into_inner().is_some() {
+                    self.emit(out, Diff::Changed(path, v1.clone(), v2.clone()));
+                }
+                return None;
+            }
+        }
+
+        Some(path)
+    }
+
+    pub(crate) fn compare_values(&self, v1: &Value, v2: &Value, path: String, out: &mut dyn DiffSink) {
+        match (v1, v2) {
+            (Value::Dict(dict1), Value::Dict(dict2)) => self.compare_dicts(dict1, dict2, path, out),
+            (Value::Array(arr1), Value::Array(arr2)) => self.compare_arrays(arr1, arr2, path, out),
+            (Value::Array(arr1), _) if self.unwrap_singleton_arrays && arr1.len() == 1 => {
+                self.compare_recursive(&arr1[0], v2, path, out)
+            }
+            (_, Value::Array(arr2)) if self.unwrap_singleton_arrays && arr2.len() == 1 => {
+                self.compare_recursive(v1, &arr2[0], path, out)
+            }
+            _ => {
+                if !self.scalars_equal(v1, v2, &path) {
+                    self.emit(out, self.changed(path, v1, v2));
+                } else if v1.as_f64().zip(v2.as_f64()).is_some_and(|(f1, f2)| f1 != f2) {
+                    out.tolerated(&self.output_path(path));
+                }
+            }
+        }
+    }
+
+    // `values_equal` plus the comparisons that only apply at matching paths.
+    fn scalars_equal(&self, v1: &Value, v2: &Value, path: &str) -> bool {
+        if self.is_unset(v1) && self.is_unset(v2) {
+            return true;
+        }
+        if self.is_string_field(path) {
+            if let Some((s1, s2)) = v1.as_text().zip(v2.as_text()) {
+                return s1 == s2;
+            }
+        }
+        if self.numeric_string_fields.iter().any(|pattern| pattern.matches(path)) {
+            if let Some(equal) = self.numeric_strings_equal(v1, v2) {
+                return equal || self.aliased(v1, v2);
+            }
+        }
+        self.values_equal(v1, v2)
+    }
+
+    pub(crate) fn has_path_filters(&self) -> bool {
+        !self.only_paths.is_empty() || !self.excluded_paths.is_empty()
+    }
+
+    fn reports_path(&self, path: &str) -> bool {
+        let segments = parse_path(path);
+        !self.excluded_paths.iter().any(|pattern| pattern.covers(&segments))
+            && (self.only_paths.is_empty() || self.only_paths.iter().any(|pattern| pattern.covers(&segments)))
+    }
+
+    pub(crate) fn may_report_below(&self, path: &str) -> bool {
+        let segments = parse_path(path);
+        !self.excluded_paths.iter().any(|pattern| pattern.covers(&segments))
+            && (self.only_paths.is_empty()
+                || self
+                    .only_paths
+                    .iter()
+                    .any(|pattern| pattern.covers(&segments) || pattern.leads_to(&segments)))
+    }
+
+    pub(crate) fn has_string_fields(&self) -> bool {
+        !self.string_fields.is_empty()
+    }
+
+    fn is_string_field(&self, path: &str) -> bool {
+        self.string_fields.iter().any(|pattern| pattern.matches(path))
+    }
+
+    fn changed(&self, path: String, v1: &Value, v2: &Value) -> Diff {
+        if v1.is_collection() != v2.is_collection() {
+            Diff::StructureChanged(path, v1.clone(), v2.clone())
+        } else if v1.variant_order() != v2.variant_order() && !(v1.is_number() && v2.is_number()) {
+            Diff::TypeChanged(path, v1.clone(), v2.clone())
+        } else if self.annotate_numeric_delta && v1.as_f64().is_some() && v2.as_f64().is_some() {
+            Diff::NumericChanged(path, v1.clone(), v2.clone(), percent_change(v1, v2))
+        } else {
+            Diff::Changed(path, v1.clone(), v2.clone())
+        }
+    }
+
+    fn emit(&self, out: &mut dyn DiffSink, mut diff: Diff) {
+        if self.has_path_filters() && !out.probing() && !self.reports_path(diff.path()) {
+            return;
+        }
+        if self.ignore_added_empty {
+            if let Diff::Added(_, value) = &diff {
+                if value.is_empty_collection() {
+                    return;
+                }
+            }
+        }
+        if out.probing() {
+            return out.push(diff);
+        }
+        if let Some(depth) = self.value_depth_limit {
+            diff.map_values(&mut |value| truncate_depth(value, depth));
+        }
+        if self.path_format == PathFormat::JsonPointer || self.separator != "." {
+            let path = diff.path_mut();
+            *path = self.output_path(std::mem::take(path));
+        }
+        out.push(diff);
+    }
+
+    pub(crate) fn output_precision(&self) -> Option<u32> {
+        self.float_output_precision
+    }
+
+    // Rewrites an internal dotted path in the configured `PathFormat`.
+    pub(crate) fn output_path(&self, path: String) -> String {
+        match self.path_format {
+            PathFormat::Dotted if self.separator == "." => path,
+            PathFormat::Dotted => with_separator(&path, &self.separator),
+            PathFormat::JsonPointer => json_pointer(&path),
+        }
+    }
+
+    fn overlaps(&self, v1: &Value, v2: &Value) -> bool {
+        match (v1, v2) {
+            (Value::Dict(dict1), Value::Dict(dict2)) => {
+                (dict1.is_empty() && dict2.is_empty())
+                    || dict1
+                        .iter()
+                        .any(|(key, value1)| dict2.get(key).is_some_and(|value2| self.overlaps(value1, value2)))
+            }
+            (Value::Array(arr1), Value::Array(arr2)) => {
+                (arr1.is_empty() && arr2.is_empty())
+                    || arr1.iter().zip(arr2).any(|(value1, value2)| self.overlaps(value1, value2))
+            }
+            (Value::Dict(_), _) | (_, Value::Dict(_)) | (Value::Array(_), _) | (_, Value::Array(_)) => false,
+            _ => self.values_equal(v1, v2),
+        }
+    }
+
+    fn rollup(&self, v1: &Value, v2: &Value, path: String, out: &mut dyn DiffSink) {
+        match (v1, v2) {
+            (Value::Dict(dict1), Value::Dict(dict2)) if dict1.len() == 1 && dict2.len() == 1 => {
+                let (key1, value1) = dict1.iter().next().unwrap();
+                let (key2, value2) = dict2.iter().next().unwrap();
+                if key1 == key2 {
+                    return self.rollup(value1, value2, key_path(&path, key1), out);
+                }
+            }
+            (Value::Array(arr1), Value::Array(arr2)) if arr1.len() == 1 && arr2.len() == 1 => {
+                return self.rollup(&arr1[0], &arr2[0], index_path(&path, 0), out);
+            }
+            _ => {}
+        }
+
+        self.emit(out, self.changed(path, v1, v2));
+    }
+
+    fn compare_dicts(
+        &self,
+        dict1: &BTreeMap<String, Value>,
+        dict2: &BTreeMap<String, Value>,
+        path: String,
+        out: &mut dyn DiffSink,
+    ) {
+        if self.summarizes(dict1, dict2) {
+            let mut diffs = Vec::new();
+            for (key, value1, value2) in self.dict_entries(dict1, dict2, &path) {
+                self.compare_entry(key, value1, value2, &path, &mut diffs);
+            }
+            if !diffs.is_empty() {
+                self.emit(out, Diff::Summarized(path, DiffSummary::from_diffs(diffs)));
+            }
+            return;
+        }
+
+        #[cfg(feature = "parallel")]
+        if self.parallel && dict1.len().max(dict2.len()) >= crate::parallel::MIN_PARALLEL_ENTRIES {
+            let entries = self.dict_entries(dict1, dict2, &path).collect();
+            return self.compare_entries_parallel(entries, &path, out);
+        }
+
+        for (key, value1, value2) in self.dict_entries(dict1, dict2, &path) {
+            if out.is_full() {
+                return;
+            }
+            self.compare_entry(key, value1, value2, &path, out);
+        }
+    }
+
+    // Whether `dict_summary_threshold` reports these dicts as one summary.
+    pub(crate) fn summarizes(&self, dict1: &BTreeMap<String, Value>, dict2: &BTreeMap<String, Value>) -> bool {
+        self.dict_summary_threshold
+            .is_some_and(|threshold| dict1.len().max(dict2.len()) > threshold)
+    }
+
+    // Pairs up the entries of two dicts in the order they are compared,
+    // lazily so an early exit doesn't pay for the whole dict.
+    pub(crate) fn dict_entries<'a>(
+        &'a self,
+        dict1: &'a BTreeMap<String, Value>,
+        dict2: &'a BTreeMap<String, Value>,
+        path: &str,
+    ) -> Box<dyn Iterator<Item = DictEntry<'a>> + 'a> {
+        if path.is_empty() {
+            if let Some(keys) = &self.top_level_keys {
+                return Box::new(
+                    keys.iter()
+                        .map(|key| (key.as_str(), dict1.get(key), dict2.get(key))),
+                );
+            }
+        }
+
+        if self.case_insensitive_keys || self.key_normalizer.is_some() {
+            return Box::new(self.dict_entries_folded(dict1, dict2).into_iter());
+        }
+
+        let shared = dict1
+            .iter()
+            .map(|(key, value1)| (key.as_str(), Some(value1), dict2.get(key)));
+        let added = dict2
+            .iter()
+            .filter(|(key, _)| !dict1.contains_key(*key))
+            .map(|(key, value2)| (key.as_str(), None, Some(value2)));
+        Box::new(shared.chain(added))
+    }
+
+    fn dict_entries_folded<'a>(
+        &self,
+        dict1: &'a BTreeMap<String, Value>,
+        dict2: &'a BTreeMap<String, Value>,
+    ) -> Vec<DictEntry<'a>> {
+        // Keys folding alike to an earlier key of the same dict stand alone.
+        let mut unmatched2 = Vec::new();
+        let mut folded2: BTreeMap<String, (&String, &Value)> = BTreeMap::new();
+        for (key, value) in dict2 {
+            match folded2.entry(self.fold_key(key)) {
+                Entry::Vacant(slot) => {
+                    slot.insert((key, value));
+                }
+                Entry::Occupied(_) => unmatched2.push((key.as_str(), None, Some(value))),
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut seen = BTreeSet::new();
+        for (key, value1) in dict1 {
+            let folded = self.fold_key(key);
+            let value2 = if seen.contains(&folded) {
+                None
+            } else {
+                folded2.get(&folded).map(|&(_, value)| value)
+            };
+            entries.push((key.as_str(), Some(value1), value2));
+            seen.insert(folded);
+        }
+
+        for (folded, (key, value2)) in &folded2 {
+            if !seen.contains(folded) {
+                entries.push((key.as_str(), None, Some(*value2)));
+            }
+        }
+        entries.extend(unmatched2);
+        entries
+    }
+
+    fn fold_key(&self, key: &str) -> String {
+        let key = match &self.key_normalizer {
+            Some(normalizer) => Cow::Owned(normalizer(key)),
+            None => Cow::Borrowed(key),
+        };
+        if self.case_insensitive_keys {
+            key.to_lowercase()
+        } else {
+            key.into_owned()
+        }
+    }
+
+    /// Compares two top-level objects one key at a time, yielding each key
+    /// with the diffs found under it. Inputs that aren't both objects yield a
+    /// single group under the empty key.
+    pub fn compare_dicts_stream<'a>(
+        &'a self,
+        d1: &'a Value,
+        d2: &'a Value,
+    ) -> impl Iterator<Item = (String, Vec<Diff>)> + 'a {
+        let (entries, whole) = match (d1, d2) {
+            (Value::Dict(dict1), Value::Dict(dict2)) => (self.dict_entries(dict1, dict2, ""), None),
+            _ => (
+                Box::new(std::iter::empty()) as Box<dyn Iterator<Item = DictEntry<'a>>>,
+                Some((String::new(), self.compare(d1, d2))),
+            ),
+        };
+
+        whole.into_iter().chain(entries.into_iter().map(move |(key, value1, value2)| {
+            let mut diffs = Vec::new();
+            self.compare_entry(key, value1, value2, "", &mut diffs);
+            (key.to_string(), diffs)
+        }))
+    }
+
+    // Whether two values that are `==` can't differ, which NaN breaks under
+    // `nan_not_equal`.
+    pub(crate) fn skips_equal(&self) -> bool {
+        !self.nan_not_equal
+    }
+
+    pub(crate) fn ignores_key(&self, key: &str) -> bool {
+        !self.ignored_keys.is_empty() && self.ignored_keys.contains(key)
+    }
+
+    // Whether dicts can be compared key by key without bypassing an option
+    // that looks at a whole dict or document, as `compare_shared` needs.
+    pub(crate) fn compares_dicts_by_key(&self) -> bool {
+        !self.case_insensitive_keys
+            && self.key_normalizer.is_none()
+            && self.top_level_keys.is_none()
+            && self.dict_summary_threshold.is_none()
+            && !self.rollup_subtrees
+            && self.max_depth.is_none()
+            && self.unwrap_path.is_none()
+    }
+
+    pub(crate) fn compare_entry(
+        &self,
+        key: &str,
+        value1: Option<&Value>,
+        value2: Option<&Value>,
+        path: &str,
+        out: &mut dyn DiffSink,
+    ) {
+        if let Some((v1, v2, new_path)) = self.visit_entry(key, value1, value2, path, out) {
+            self.compare_recursive(v1, v2, new_path, out);
+        }
+    }
+
+    // `compare_entry` up to the recursion: reports a key present on one side
+    // and returns the two values to compare when it is on both.
+    pub(crate) fn visit_entry<'v>(
+        &self,
+        key: &str,
+        value1: Option<&'v Value>,
+        value2: Option<&'v Value>,
+        path: &str,
+        out: &mut dyn DiffSink,
+    ) -> Option<(&'v Value, &'v Value, String)> {
+        if self.ignores_key(key) {
+            return None;
+        }
+        // Same early exit as `compare_recursive`, before paying for the path.
+        if let (Some(v1), Some(v2)) = (value1, value2) {
+            if !self.nan_not_equal && v1 == v2 {
+                out.enter();
+                return None;
+            }
+        }
+
+        let new_path = key_path(path, key);
+        match (value1, value2) {
+            (Some(v1), Some(v2)) => return Some((v1, v2, new_path)),
+            (Some(value), None) | (None, Some(value)) if self.is_default(&new_path, value) || self.is_unset(value) => {}
+            (Some(v1), None) => self.emit(out, Diff::Removed(new_path, v1.clone())),
+            (None, Some(v2)) => self.emit(out, Diff::Added(new_path, v2.clone())),
+            (None, None) => {}
+        }
+        None
+    }
+
+    fn is_unset(&self, value: &Value) -> bool {
+        self.unset_sentinels.contains(value)
+    }
+
+    fn is_default(&self, path: &str, value: &Value) -> bool {
+        self.defaults.get(path).is_some_and(|default| self.deep_equal(default, value, path))
+    }
+
+    fn compare_arrays(&self, arr1: &[Value], arr2: &[Value], path: String, out: &mut dyn DiffSink) {
+        if let Some(key) = self.element_key() {
+            self.compare_arrays_by_identity(arr1, arr2, key.as_ref(), path, out)
+        } else if self.ignores_order_at(&path) {
+            self.compare_arrays_unordered(arr1, arr2, path, out)
+        } else if let Some(key) = &self.canonical_order_key {
+            let sorted1 = sort_by_field(arr1, key);
+            let sorted2 = sort_by_field(arr2, key);
+            self.compare_arrays_ordered(&sorted1, &sorted2, path, out)
+        } else if self.array_algorithm == ArrayAlgo::Lcs {
+            self.compare_arrays_lcs(arr1, arr2, path, out)
+        } else {
+            self.compare_arrays_ordered(arr1, arr2, path, out)
+        }
+    }
+
+    fn compare_arrays_lcs(&self, arr1: &[Value], arr2: &[Value], path: String, out: &mut dyn DiffSink) {
+        let alignment = self.lcs_alignment(arr1, arr2, &path);
+
+        // Unmatched runs before the first and after the last matched pair are
+        // dropped when they fit within the configured edge tolerance.
+        let first_match = alignment.iter().position(|pair| matches!(pair, (Some(_), Some(_))));
+        let last_match = alignment.iter().rposition(|pair| matches!(pair, (Some(_), Some(_))));
+        let (start, end) = match (first_match, last_match) {
+            (Some(first), Some(last)) => {
+                let (leading, trailing) = self.tolerated_edge_edits;
+                let start = if first <= leading { first } else { 0 };
+                let end = if alignment.len() - last - 1 <= trailing { last + 1 } else { alignment.len() };
+                (start, end)
+            }
+            _ => (0, alignment.len()),
+        };
+
+        for pair in &alignment[start..end] {
+            if out.is_full() {
+                return;
+            }
+            match *pair {
+                (Some(i), Some(j)) => self.compare_elements(&arr1[i], &arr2[j], index_path(&path, i), out),
+                (Some(i), None) => self.emit(out, Diff::Removed(index_path(&path, i), arr1[i].clone())),
+                (None, Some(j)) => self.emit(out, Diff::Added(index_path(&path, j), arr2[j].clone())),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn compare_arrays_ordered(
+        &self,
+        arr1: &[Value],
+        arr2: &[Value],
+        path: String,
+        out: &mut dyn DiffSink,
+    ) {
+        let max_len = arr1.len().max(arr2.len());
+
+        for i in 0..max_len {
+            if out.is_full() {
+                return;
+            }
+            let new_path = index_path(&path, i);
+            match (arr1.get(i), arr2.get(i)) {
+                (Some(v1), Some(v2)) => {
+                    self.compare_elements(v1, v2, new_path, out);
+                }
+                (Some(v1), None) => self.emit(out, Diff::Removed(new_path, v1.clone())),
+                (None, Some(v2)) => self.emit(out, Diff::Added(new_path, v2.clone())),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn compare_elements(&self, v1: &Value, v2: &Value, path: String, out: &mut dyn DiffSink) {
+        if self.same_structure(v1, v2) {
+            return;
+        }
+        self.compare_recursive(v1, v2, path, out);
+    }
+
+    // Whether `array_structure_only` ignores the change between two elements.
+    fn same_structure(&self, v1: &Value, v2: &Value) -> bool {
+        self.array_structure_only
+            && !v1.is_collection()
+            && !v2.is_collection()
+            && v1.type_name() == v2.type_name()
+    }
+
+    fn compare_arrays_by_identity(
+        &self,
+        arr1: &[Value],
+        arr2: &[Value],
+        identity: &ArrayIdentity,
+        path: String,
+        out: &mut dyn DiffSink,
+    ) {
+        for pair in self.identity_alignment(arr1, arr2, identity) {
+            if out.is_full() {
+                return;
+            }
+            match pair {
+                (Some(i), Some(j)) => {
+                    self.compare_elements(&arr1[i], &arr2[j], self.keyed_path(&path, &arr1[i], i), out);
+                }
+                (Some(i), None) => {
+                    self.emit(out, Diff::Removed(self.keyed_path(&path, &arr1[i], i), arr1[i].clone()))
+                }
+                (None, Some(j)) => {
+                    self.emit(out, Diff::Added(self.keyed_path(&path, &arr2[j], j), arr2[j].clone()))
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    // The path of an element matched by identity: `[field=value]` under
+    // `array_key`, otherwise its index.
+    fn keyed_path(&self, path: &str, element: &Value, index: usize) -> String {
+        let key = self.array_key.as_ref().and_then(|field| match element {
+            Value::Dict(dict) => dict.get(field).map(|value| (field, value)),
+            _ => None,
+        });
+        match key {
+            Some((field, value)) => format!("{}[{}={}]", path, field, canonical_json(value)),
+            None => index_path(path, index),
+        }
+    }
+
+    fn compare_arrays_unordered(
+        &self,
+        arr1: &[Value],
+        arr2: &[Value],
+        path: String,
+        out: &mut dyn DiffSink,
+    ) {
+        let element_path = |i: usize| {
+            if self.unordered_source_indices {
+                index_path(&path, i)
+            } else {
+                path.clone()
+            }
+        };
+
+        let alignment = self.unordered_alignment(arr1, arr2, &path);
+        if self.report_reorders
+            && alignment.iter().all(|pair| matches!(pair, (Some(_), Some(_))))
+            && alignment.iter().any(|(i, j)| i != j)
+        {
+            self.emit(out, Diff::Reordered(path));
+            return;
+        }
+
+        for pair in alignment {
+            if out.is_full() {
+                return;
+            }
+            match pair {
+                (Some(i), Some(j)) => {
+                    self.compare_elements(&arr1[i], &arr2[j], element_path(i), out);
+                }
+                (Some(i), None) if self.is_ignored_array_value(&arr1[i]) => {}
+                (None, Some(j)) if self.is_ignored_array_value(&arr2[j]) => {}
+                (Some(i), None) => self.emit(out, Diff::Removed(element_path(i), arr1[i].clone())),
+                (None, Some(j)) => self.emit(out, Diff::Added(element_path(j), arr2[j].clone())),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    fn is_ignored_array_value(&self, value: &Value) -> bool {
+        self.ignored_array_values.iter().any(|ignored| self.values_equal(ignored, value))
+    }
+
+    /// Pairs up the elements of two root arrays the way `compare` does,
+    /// returning their indices, with `None` for an element left unmatched.
+    pub fn array_alignment(&self, a: &[Value], b: &[Value]) -> Vec<(Option<usize>, Option<usize>)> {
+        if let Some(key) = self.element_key() {
+            self.identity_alignment(a, b, key.as_ref())
+        } else if self.ignores_order_at("") {
+            self.unordered_alignment(a, b, "")
+        } else if let Some(key) = &self.canonical_order_key {
+            let (order1, order2) = (field_order(a, key), field_order(b, key));
+            (0..a.len().max(b.len()))
+                .map(|i| (order1.get(i).copied(), order2.get(i).copied()))
+                .collect()
+        } else if self.array_algorithm == ArrayAlgo::Lcs {
+            self.lcs_alignment(a, b, "")
+        } else {
+            (0..a.len().max(b.len()))
+                .map(|i| ((i < a.len()).then_some(i), (i < b.len()).then_some(i)))
+                .collect()
+        }
+    }
+
+    fn element_key(&self) -> Option<Arc<ArrayIdentity>> {
+        let fields: Vec<String> = self.array_key.iter().chain(&self.discriminator_field).cloned().collect();
+        if fields.is_empty() {
+            return self.array_identity.clone();
+        }
+        let identity = self.array_identity.clone();
+        Some(Arc::new(move |value: &Value| {
+            let mut parts: Vec<Value> = fields
+                .iter()
+                .map(|field| match value {
+                    Value::Dict(dict) => dict.get(field).cloned().unwrap_or(Value::Null),
+                    _ => Value::Null,
+                })
+                .collect();
+            if let Some(identity) = &identity {
+                parts.push(identity(value));
+            }
+            match parts.len() {
+                1 => parts.pop().unwrap(),
+                _ => Value::Array(parts),
+            }
+        }))
+    }
+
+    fn identity_alignment(
+        &self,
+        a: &[Value],
+        b: &[Value],
+        identity: &ArrayIdentity,
+    ) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut candidates: BTreeMap<Value, VecDeque<usize>> = BTreeMap::new();
+        for (j, value) in b.iter().enumerate() {
+            candidates.entry(identity(value)).or_default().push_back(j);
+        }
+
+        let mut matched = vec![false; b.len()];
+        let mut pairs = Vec::with_capacity(a.len().max(b.len()));
+        for (i, value) in a.iter().enumerate() {
+            match candidates.get_mut(&identity(value)).and_then(|queue| queue.pop_front()) {
+                Some(j) => {
+                    matched[j] = true;
+                    pairs.push((Some(i), Some(j)));
+                }
+                None => pairs.push((Some(i), None)),
+            }
+        }
+        pairs.extend((0..b.len()).filter(|&j| !matched[j]).map(|j| (None, Some(j))));
+
+        pairs
+    }
+
+    // Merge-joins both sides in sorted order, so elements pair up as a
+    // multiset: duplicates match one-for-one and the surplus on either side
+    // is reported as removed or added.
+    fn unordered_alignment(&self, a: &[Value], b: &[Value], path: &str) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut order1: Vec<usize> = (0..a.len()).collect();
+        let mut order2: Vec<usize> = (0..b.len()).collect();
+
+        order1.sort_by(|&i, &j| a[i].cmp(&a[j]));
+        order2.sort_by(|&i, &j| b[i].cmp(&b[j]));
+
+        let mut pairs = Vec::with_capacity(a.len().max(b.len()));
+        let (mut x, mut y) = (0, 0);
+        while x < order1.len() && y < order2.len() {
+            let (i, j) = (order1[x], order2[y]);
+            match a[i].cmp(&b[j]) {
+                Ordering::Equal => {
+                    pairs.push((Some(i), Some(j)));
+                    x += 1;
+                    y += 1;
+                }
+                _ if self.elements_equal(&a[i], &b[j], path, i) => {
+                    pairs.push((Some(i), Some(j)));
+                    x += 1;
+                    y += 1;
+                }
+                Ordering::Less => {
+                    pairs.push((Some(i), None));
+                    x += 1;
+                }
+                Ordering::Greater => {
+                    pairs.push((None, Some(j)));
+                    y += 1;
+                }
+            }
+        }
+        pairs.extend(order1[x..].iter().map(|&i| (Some(i), None)));
+        pairs.extend(order2[y..].iter().map(|&j| (None, Some(j))));
+
+        pairs
+    }
+
+    fn lcs_alignment(&self, a: &[Value], b: &[Value], path: &str) -> Vec<(Option<usize>, Option<usize>)> {
+        let (n, m) = (a.len(), b.len());
+        let mut table = vec![vec![0usize; m + 1]; n + 1];
+        let mut equal = vec![vec![false; m]; n];
+
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                equal[i][j] = self.elements_equal(&a[i], &b[j], path, i);
+                table[i][j] = if equal[i][j] {
+                    table[i + 1][j + 1] + 1
+                } else {
+                    table[i + 1][j].max(table[i][j + 1])
+                };
+            }
+        }
+
+        let mut pairs = Vec::with_capacity(n.max(m));
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if equal[i][j] {
+                pairs.push((Some(i), Some(j)));
+                i += 1;
+                j += 1;
+            } else if table[i + 1][j] >= table[i][j + 1] {
+                pairs.push((Some(i), None));
+                i += 1;
+            } else {
+                pairs.push((None, Some(j)));
+                j += 1;
+            }
+        }
+        pairs.extend((i..n).map(|i| (Some(i), None)));
+        pairs.extend((j..m).map(|j| (None, Some(j))));
+
+        pairs
+    }
+
+    fn ignores_order_at(&self, path: &str) -> bool {
+        if self.order_by_depth.is_empty() {
+            return self.ignore_order;
+        }
+        let depth = parse_path(path)
+            .iter()
+            .filter(|segment| matches!(segment, PathSegment::Index(_) | PathSegment::Keyed(..)))
+            .count();
+        self.order_by_depth.get(depth).copied().unwrap_or(self.ignore_order)
+    }
+
+    fn elements_equal(&self, v1: &Value, v2: &Value, path: &str, index: usize) -> bool {
+        self.same_structure(v1, v2) || self.deep_equal(v1, v2, &index_path(path, index))
+    }
+
+    // Compares under the values' real path, so path-scoped options apply as
+    // they would in the full comparison, while path filters, which only
+    // decide what gets reported, don't hide differences.
+    fn deep_equal(&self, v1: &Value, v2: &Value, path: &str) -> bool {
+        let mut probe = Probe::default();
+        self.compare_recursive(v1, v2, path.to_string(), &mut probe);
+        !probe.differs()
+    }
+
+    fn values_equal(&self, v1: &Value, v2: &Value) -> bool {
+        if self.numeric_strings {
+            if let Some(equal) = self.numeric_strings_equal(v1, v2) {
+                return equal || self.aliased(v1, v2);
+            }
+        }
+
+        let equal = match (v1, v2) {
+            (Value::Float(f1), Value::Float(f2)) => self.floats_equal(*f1, *f2),
+            (Value::Float(f), other) | (other, Value::Float(f)) if self.numeric_coercion && other.is_number() => {
+                self.floats_equal(*f, other.as_f64().unwrap())
+            }
+            (Value::String(s1), Value::String(s2)) => self.strings_equal(s1, s2),
+            (Value::Bool(b), Value::String(s)) | (Value::String(s), Value::Bool(b))
+                if self.bool_string_equivalence =>
+            {
+                s.eq_ignore_ascii_case(if *b { "true" } else { "false" })
+            }
+            _ => v1 == v2,
+        };
+        equal || self.aliased(v1, v2)
+    }
+
+    fn floats_equal(&self, f1: f64, f2: f64) -> bool {
+        if f1.is_nan() && f2.is_nan() {
+            !self.nan_not_equal
+        } else if f1 == f2 {
+            true
+        } else if let Some(figures) = self.float_sig_figs {
+            round_sig_figs(f1, figures) == round_sig_figs(f2, figures)
+        } else if let Some(tolerance) = self.float_tolerance {
+            let diff = (f1 - f2).abs();
+            if self.use_percent {
+                let max = f1.abs().max(f2.abs());
+                max == 0.0 || diff / max <= tolerance
+            } else {
+                diff <= tolerance
+            }
+        } else {
+            f1 == f2
+        }
+    }
+
+    fn strings_equal(&self, s1: &str, s2: &str) -> bool {
+        if self.datetime_aware {
+            if let (Some(t1), Some(t2)) = (parse_rfc3339(s1), parse_rfc3339(s2)) {
+                return (t1 - t2).abs() <= self.datetime_tolerance.as_nanos() as i128;
+            }
+        }
+        if s1 == s2 {
+            return true;
+        }
+        let (s1, s2) = if self.ignore_line_ending_differences {
+            (normalize_line_endings(s1), normalize_line_endings(s2))
+        } else {
+            (Cow::Borrowed(s1), Cow::Borrowed(s2))
+        };
+        s1 == s2 || (self.case_insensitive_strings && s1.to_lowercase() == s2.to_lowercase())
+    }
+
+    fn numeric_strings_equal(&self, v1: &Value, v2: &Value) -> Option<bool> {
+        if !matches!(v1, Value::String(_)) && !matches!(v2, Value::String(_)) {
+            return None;
+        }
+
+        match (self.as_number(v1)?, self.as_number(v2)?) {
+            (a, b) if a.as_i128().is_some() && b.as_i128().is_some() => Some(a == b),
+            (a, b) => Some(self.values_equal(&Value::Float(a.as_f64()?), &Value::Float(b.as_f64()?))),
+        }
+    }
+
+    fn as_number(&self, value: &Value) -> Option<Value> {
+        match value {
+            Value::Int(_) | Value::UInt(_) | Value::Float(_) => Some(value.clone()),
+            Value::String(s) => {
+                let numeric = !s.is_empty()
+                    && s.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E'));
+                if !numeric || (self.preserve_zero_padding && is_zero_padded(s)) {
+                    return None;
+                }
+                s.parse::<i64>()
+                    .map(Value::Int)
+                    .or_else(|_| s.parse::<u64>().map(Value::UInt))
+                    .ok()
+                    .or_else(|| s.parse::<f64>().ok().map(Value::Float))
+            }
+            _ => None,
+        }
+    }
+
+    fn aliased(&self, v1: &Value, v2: &Value) -> bool {
+        self.value_aliases
+            .iter()
+            .any(|group| group.contains(v1) && group.contains(v2))
+    }
+
+    pub fn compare_json(&self, json1: &JsonValue, json2: &JsonValue) -> Vec<Diff> {
+        if self.has_string_fields() {
+            let v1 = self.json_to_value_at(json1, String::new());
+            let v2 = self.json_to_value_at(json2, String::new());
+            return self.compare(&v1, &v2);
+        }
+
+        let v1 = self.json_to_value(json1);
+        let v2 = self.json_to_value(json2);
+        self.compare(&v1, &v2)
+    }
+
+    // Like `json_to_value`, but keeps numbers under string-compared fields
+    // as their exact text.
+    fn json_to_value_at(&self, json: &JsonValue, path: String) -> Value {
+        match json {
+            JsonValue::Number(n) if self.is_string_field(&path) => Value::String(n.to_string()),
+            JsonValue::Array(arr) => Value::Array(
+                arr.iter()
+                    .enumerate()
+                    .map(|(i, v)| self.json_to_value_at(v, index_path(&path, i)))
+                    .collect(),
+            ),
+            JsonValue::Object(obj) => Value::Dict(
+                obj.iter()
+                    .map(|(k, v)| (k.clone(), self.json_to_value_at(v, key_path(&path, k))))
+                    .collect(),
+            ),
+            _ => Value::from(json),
+        }
+    }
+
+    pub(crate) fn json_to_value(&self, json: &JsonValue) -> Value {
+        Value::from(json)
+    }
+}
+
+pub(crate) fn round_floats(value: &mut Value, decimals: u32) {
+    match value {
+        Value::Float(f) => {
+            let factor = 10f64.powi(decimals as i32);
+            let scaled = *f * factor;
+            // Floats this large have no digits past the point to round.
+            if scaled.is_finite() {
+                *f = scaled.round() / factor;
+            }
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(|v| round_floats(v, decimals)),
+        Value::Dict(dict) => dict.values_mut().for_each(|v| round_floats(v, decimals)),
+        _ => {}
+    }
+}
+
+fn is_zero_padded(s: &str) -> bool {
+    let digits = s.trim_start_matches(['-', '+']);
+    digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit()
+}
+
+fn truncate_depth(value: &mut Value, depth: usize) {
+    if !value.is_collection() {
+        return;
+    }
+    if depth == 0 {
+        *value = Value::String(TRUNCATED_MARKER.to_string());
+        return;
+    }
+    match value {
+        Value::Array(arr) => arr.iter_mut().for_each(|v| truncate_depth(v, depth - 1)),
+        Value::Dict(dict) => dict.values_mut().for_each(|v| truncate_depth(v, depth - 1)),
+        _ => {}
+    }
+}
+
+fn round_sig_figs(f: f64, figures: u32) -> f64 {
+    if f == 0.0 || !f.is_finite() {
+        return f;
+    }
+    let magnitude = f.abs().log10().floor() as i32;
+    let factor = 10f64.powi(figures as i32 - 1 - magnitude);
+    (f * factor).round() / factor
+}
+
+fn normalize_line_endings(s: &str) -> Cow<'_, str> {
+    if !s.contains('\r') && !s.ends_with('\n') {
+        return Cow::Borrowed(s);
+    }
+    let normalized = s.replace("\r\n", "\n").replace('\r', "\n");
+    Cow::Owned(normalized.trim_end_matches('\n').to_string())
+}
+
+fn sort_by_field(arr: &[Value], key: &str) -> Vec<Value> {
+    field_order(arr, key).into_iter().map(|i| arr[i].clone()).collect()
+}
+
+// The indices of `arr` in the order `sort_by_field` puts its elements.
+fn field_order(arr: &[Value], key: &str) -> Vec<usize> {
+    let field = |i: &usize| match &arr[*i] {
+        Value::Dict(dict) => dict.get(key).cloned(),
+        _ => None,
+    };
+    let mut order: Vec<usize> = (0..arr.len()).collect();
+    order.sort_by_cached_key(field);
+    order
+}
+
+fn percent_change(old: &Value, new: &Value) -> Option<f64> {
+    match (old.as_f64(), new.as_f64()) {
+        (Some(old), Some(new)) if old != 0.0 => Some((new - old) / old.abs() * 100.0),
+        _ => None,
+    }
+}
+
+pub(crate) fn key_path(path: &str, key: &str) -> String {
+    let mut new_path = String::with_capacity(path.len() + 1 + key.len());
+    new_path.push_str(path);
+    push_key(&mut new_path, key);
+    new_path
+}
+
+pub(crate) fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+pub fn diffs_to_json(diffs: &[Diff]) -> JsonValue {
+    serde_json::to_value(diffs).unwrap()
+}
+
+#[cfg(feature = "yaml")]
+pub fn diffs_to_yaml(diffs: &[Diff]) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(diffs)
+}
+
+/// Returns the top-level keys whose subtrees differ in any way, including
+/// keys present on one side only. Uses strict equality, so none of the
+/// `DeepDiff` options apply. Non-object inputs that differ report `""`.
+pub fn changed_top_level_keys(v1: &Value, v2: &Value) -> BTreeSet<String> {
+    match (v1, v2) {
+        (Value::Dict(dict1), Value::Dict(dict2)) => dict1
+            .keys()
+            .chain(dict2.keys())
+            .filter(|key| dict1.get(*key) != dict2.get(*key))
+            .cloned()
+            .collect(),
+        _ if v1 == v2 => BTreeSet::new(),
+        _ => BTreeSet::from([String::new()]),
+    }
+}
+
+/// Hashes the set of changed paths and the kind of each change, ignoring the
+/// values, so two runs that touched the same fields share a signature.
+pub fn changed_path_signature(diffs: &[Diff]) -> u64 {
+    let changes: BTreeSet<(&str, &str)> = diffs.iter().map(|diff| (diff.path(), diff.kind())).collect();
+    let mut hasher = Fnv1a::default();
+    changes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A hex id for the logical change a diff set makes, like `git patch-id`:
+/// each diff is normalized to its kind, path and RFC 8785 canonical values,
+/// and the results are hashed in sorted order so diff order doesn't matter.
+pub fn patch_id(diffs: &[Diff]) -> String {
+    let mut changes: Vec<Vec<u8>> = diffs.iter().map(canonical_change).collect();
+    changes.sort();
+    // Lengths are written as fixed-width little-endian so the id is the same
+    // on every platform, which `Hash for Vec` doesn't promise.
+    let mut hasher = Fnv1a::default();
+    for change in &changes {
+        hasher.write(&(change.len() as u64).to_le_bytes());
+        hasher.write(change);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+fn canonical_change(diff: &Diff) -> Vec<u8> {
+    fn push_value(bytes: &mut Vec<u8>, value: &Value) {
+        bytes.push(0);
+        bytes.extend(to_canonical_bytes(value));
+    }
+
+    let mut bytes = format!("{}\0{}", diff.kind(), diff.path()).into_bytes();
+    match diff {
+        Diff::Added(_, value) | Diff::Removed(_, value) => push_value(&mut bytes, value),
+        Diff::Changed(_, old, new)
+        | Diff::StructureChanged(_, old, new)
+        | Diff::TypeChanged(_, old, new)
+        | Diff::NumericChanged(_, old, new, _) => {
+            push_value(&mut bytes, old);
+            push_value(&mut bytes, new);
+        }
+        Diff::Summarized(_, summary) => {
+            bytes.extend(format!("\0{} {} {}", summary.added, summary.removed, summary.changed).bytes());
+            for sampled in &summary.sample {
+                bytes.push(0);
+                bytes.extend(canonical_change(sampled));
+            }
+        }
+        Diff::Reordered(_) => {}
+    }
+    bytes
+}
+
+/// Returns how many levels down `v1` and `v2` stay identical: the length of
+/// the shortest path to a difference. Identical documents return their full
+/// depth, the length of the longest path to a leaf, which a difference at
+/// that leaf also gives; compare with `==` to tell the two apart.
+pub fn common_prefix_depth(v1: &Value, v2: &Value) -> usize {
+    divergence_depth(v1, v2, 0).unwrap_or_else(|| nesting_depth(v1))
+}
+
+fn divergence_depth(v1: &Value, v2: &Value, depth: usize) -> Option<usize> {
+    if v1 == v2 {
+        return None;
+    }
+    match (v1, v2) {
+        (Value::Dict(dict1), Value::Dict(dict2)) => {
+            if dict1.len() != dict2.len() || dict1.keys().any(|key| !dict2.contains_key(key)) {
+                return Some(depth + 1);
+            }
+            dict1
+                .iter()
+                .filter_map(|(key, value1)| divergence_depth(value1, &dict2[key], depth + 1))
+                .min()
+        }
+        (Value::Array(arr1), Value::Array(arr2)) => {
+            if arr1.len() != arr2.len() {
+                return Some(depth + 1);
+            }
+            arr1.iter()
+                .zip(arr2)
+                .filter_map(|(value1, value2)| divergence_depth(value1, value2, depth + 1))
+                .min()
+        }
+        _ => Some(depth),
+    }
+}
+
+fn nesting_depth(value: &Value) -> usize {
+    match value {
+        Value::Array(arr) => arr.iter().map(|v| nesting_depth(v) + 1).max().unwrap_or(0),
+        Value::Dict(dict) => dict.values().map(|v| nesting_depth(v) + 1).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Returns the paths of object keys that exist only in `v1` and only in `v2`,
+/// ignoring any change in the values of keys present on both sides.
+pub fn key_diff(v1: &Value, v2: &Value) -> (Vec<String>, Vec<String>) {
+    let mut only1 = Vec::new();
+    let mut only2 = Vec::new();
+    collect_key_diff(v1, v2, "", &mut only1, &mut only2);
+    (only1, only2)
+}
+
+fn collect_key_diff(v1: &Value, v2: &Value, path: &str, only1: &mut Vec<String>, only2: &mut Vec<String>) {
+    match (v1, v2) {
+        (Value::Dict(dict1), Value::Dict(dict2)) => {
+            for (key, value1) in dict1 {
+                match dict2.get(key) {
+                    Some(value2) => collect_key_diff(value1, value2, &key_path(path, key), only1, only2),
+                    None => only1.push(key_path(path, key)),
+                }
+            }
+            for key in dict2.keys() {
+                if !dict1.contains_key(key) {
+                    only2.push(key_path(path, key));
+                }
+            }
+        }
+        (Value::Array(arr1), Value::Array(arr2)) => {
+            for (i, (value1, value2)) in arr1.iter().zip(arr2).enumerate() {
+                collect_key_diff(value1, value2, &index_path(path, i), only1, only2);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Groups diffs into batches whose serialized JSON array stays within
+/// `max_bytes`. A diff that is larger than the budget on its own gets a batch
+/// to itself.
+pub fn chunk_diffs(diffs: Vec<Diff>, max_bytes: usize) -> Vec<Vec<Diff>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 2;
+
+    for diff in diffs {
+        let size = serde_json::to_string(&diff).unwrap().len();
+        if !batch.is_empty() && batch_bytes + 1 + size > max_bytes {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 2;
+        }
+        batch_bytes += if batch.is_empty() { size } else { size + 1 };
+        batch.push(diff);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+
+    batches
+}
+
+/// Reverses diffs so they lead from the compared target back to the source,
+/// i.e. `apply(b, &invert(&compare(a, b)))` rebuilds `a`. Under
+/// `ignore_order` removals keep `b`'s indices and additions `a`'s, so the
+/// rebuilt arrays match `a` up to order.
+pub fn invert(diffs: &[Diff]) -> Vec<Diff> {
+    diffs.iter().map(Diff::inverted).collect()
+}
+
+/// Buckets diffs by the array element they fall under, keyed by the path of
+/// the deepest enclosing element (e.g. `users[2]`). Diffs outside any array
+/// are grouped under `""`.
+pub fn group_by_array_element(diffs: &[Diff]) -> BTreeMap<String, Vec<Diff>> {
+    let mut groups: BTreeMap<String, Vec<Diff>> = BTreeMap::new();
+    for diff in diffs {
+        let element = element_prefix(diff.path()).unwrap_or("");
+        groups.entry(element.to_string()).or_default().push(diff.clone());
+    }
+    groups
+}
+
+/// Replaces every diff at or below `under_path` with `updates`, keeping the
+/// position of the first replaced diff so the set stays in traversal order.
+pub fn upsert_diffs(base: &mut Vec<Diff>, updates: Vec<Diff>, under_path: &str) {
+    let position = base
+        .iter()
+        .position(|diff| is_under(diff.path(), under_path))
+        .unwrap_or(base.len());
+    base.retain(|diff| !is_under(diff.path(), under_path));
+    base.splice(position..position, updates);
+}
+
+/// Converts diffs to an RFC 6902 JSON Patch. Replacements come first, then
+/// removals in reverse order so array indices stay valid, then additions.
+/// Summaries are skipped, and unordered-array diffs without indices don't map
+/// to valid pointers.
+pub fn to_json_patch(diffs: &[Diff]) -> JsonValue {
+    let mut replaces = Vec::new();
+    let mut removes = Vec::new();
+    let mut adds = Vec::new();
+
+    for diff in diffs {
+        match diff {
+            Diff::Added(path, value) => adds.push(serde_json::json!({
+                "op": "add",
+                "path": json_pointer(path),
+                "value": value_to_json(value),
+            })),
+            Diff::Removed(path, _) => removes.push(serde_json::json!({
+                "op": "remove",
+                "path": json_pointer(path),
+            })),
+            Diff::Changed(path, _, value)
+            | Diff::StructureChanged(path, _, value)
+            | Diff::TypeChanged(path, _, value)
+            | Diff::NumericChanged(path, _, value, _) => replaces.push(serde_json::json!({
+                "op": "replace",
+                "path": json_pointer(path),
+                "value": value_to_json(value),
+            })),
+            Diff::Summarized(..) | Diff::Reordered(_) => {}
+        }
+    }
+
+    removes.reverse();
+    JsonValue::Array(replaces.into_iter().chain(removes).chain(adds).collect())
+}
+
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn json_pointer(path: &str) -> String {
+    parse_path(path)
+        .iter()
+        .map(|segment| match segment {
+            PathSegment::Key(key) => format!("/{}", escape_pointer_token(key)),
+            PathSegment::Index(index) => format!("/{}", index),
+            PathSegment::Keyed(field, value) => format!("/{}", escape_pointer_token(&format!("{}={}", field, value))),
+        })
+        .collect()
+}
+
+/// Re-nests added and changed values into a single object suitable for a
+/// partial-update (PATCH) body. Removed keys are omitted. A payload can't
+/// address array elements, so diffs inside arrays are left out too; see
+/// `to_update_payload_with_source`.
+pub fn to_update_payload(diffs: &[Diff]) -> JsonValue {
+    update_payload(diffs, None)
+}
+
+/// `to_update_payload`, with every array that has a diff inside it sent
+/// whole, as it is in `new`, the value the diffs were computed against.
+pub fn to_update_payload_with_source(diffs: &[Diff], new: &Value) -> JsonValue {
+    update_payload(diffs, Some(new))
+}
+
+fn update_payload(diffs: &[Diff], new: Option<&Value>) -> JsonValue {
+    let mut payload = JsonValue::Object(serde_json::Map::new());
+    let mut arrays = BTreeSet::new();
+
+    for diff in diffs {
+        match diff {
+            Diff::Added(path, value)
+            | Diff::Changed(path, _, value)
+            | Diff::StructureChanged(path, _, value)
+            | Diff::TypeChanged(path, _, value)
+            | Diff::NumericChanged(path, _, value, _) => {
+                let segments = parse_path(path);
+                match enclosing_array(&segments) {
+                    Some(array) => {
+                        arrays.insert(array.to_vec());
+                    }
+                    None => insert_at(&mut payload, &segments, value_to_json(value)),
+                }
+            }
+            Diff::Removed(path, _) => {
+                if let Some(array) = enclosing_array(&parse_path(path)) {
+                    arrays.insert(array.to_vec());
+                }
+            }
+            Diff::Summarized(..) | Diff::Reordered(_) => {}
+        }
+    }
+
+    if let Some(new) = new {
+        insert_arrays(&mut payload, &arrays, new);
+    }
+    payload
+}
+
+/// Builds an RFC 7396 merge patch from the diffs: additions and changes are
+/// nested as in `to_update_payload` and removed object keys become `null`.
+/// Removed array elements are left out of the patch and their paths
+/// returned separately, for consumers that can apply removals in place.
+/// Other diffs inside arrays can't be expressed and are left out; see
+/// `to_merge_patch_with_removals_with_source`.
+pub fn to_merge_patch_with_removals(diffs: &[Diff]) -> (JsonValue, Vec<String>) {
+    merge_patch_with_removals(diffs, None)
+}
+
+/// `to_merge_patch_with_removals`, with every array whose elements changed
+/// or were added replaced whole with its value in `new`, the value the diffs
+/// were computed against. Removals inside those arrays are then covered by
+/// the patch and not listed.
+pub fn to_merge_patch_with_removals_with_source(diffs: &[Diff], new: &Value) -> (JsonValue, Vec<String>) {
+    merge_patch_with_removals(diffs, Some(new))
+}
+
+fn merge_patch_with_removals(diffs: &[Diff], new: Option<&Value>) -> (JsonValue, Vec<String>) {
+    let mut patch = JsonValue::Object(serde_json::Map::new());
+    let mut arrays = BTreeSet::new();
+    let mut removals = Vec::new();
+
+    for diff in diffs {
+        let (path, value) = match diff {
+            Diff::Added(path, value)
+            | Diff::Changed(path, _, value)
+            | Diff::StructureChanged(path, _, value)
+            | Diff::TypeChanged(path, _, value)
+            | Diff::NumericChanged(path, _, value, _) => (path, value_to_json(value)),
+            Diff::Removed(path, _) => (path, JsonValue::Null),
+            Diff::Summarized(..) | Diff::Reordered(_) => continue,
+        };
+        let segments = parse_path(path);
+        match enclosing_array(&segments) {
+            None => insert_at(&mut patch, &segments, value),
+            Some(array) if matches!(diff, Diff::Removed(..)) && array.len() + 1 == segments.len() => {
+                removals.push(path.clone());
+            }
+            Some(array) => {
+                arrays.insert(array.to_vec());
+            }
+        }
+    }
+
+    let Some(new) = new else {
+        return (patch, removals);
+    };
+    insert_arrays(&mut patch, &arrays, new);
+    removals.retain(|path| {
+        enclosing_array(&parse_path(path)).is_some_and(|array| !arrays.contains(array))
+    });
+    (patch, removals)
+}
+
+// The path of the outermost array `segments` runs through, if any.
+fn enclosing_array(segments: &[PathSegment]) -> Option<&[PathSegment]> {
+    let index = segments
+        .iter()
+        .position(|segment| matches!(segment, PathSegment::Index(_) | PathSegment::Keyed(..)))?;
+    Some(&segments[..index])
+}
+
+fn insert_arrays(target: &mut JsonValue, arrays: &BTreeSet<Vec<PathSegment>>, new: &Value) {
+    for array in arrays {
+        if let Some(value) = lookup(new, array) {
+            insert_at(target, array, value_to_json(value));
+        }
+    }
+}
+
+// Callers only pass object keys here; arrays are written whole.
+fn insert_at(target: &mut JsonValue, segments: &[PathSegment], value: JsonValue) {
+    match segments.split_first() {
+        None => *target = value,
+        Some((PathSegment::Key(key), rest)) => {
+            if !target.is_object() {
+                *target = JsonValue::Object(serde_json::Map::new());
+            }
+            let child = target
+                .as_object_mut()
+                .unwrap()
+                .entry(key.clone())
+                .or_insert(JsonValue::Null);
+            insert_at(child, rest, value);
+        }
+        Some(_) => {}
+    }
+}
+
+pub(crate) fn value_to_json(value: &Value) -> JsonValue {
+    JsonValue::from(value)
+}
+
+// Where the element written `[field=value]` in a keyed path sits in `arr`.
+pub(crate) fn keyed_position(arr: &[Value], field: &str, value: &str) -> Option<usize> {
+    arr.iter().position(|element| match element {
+        Value::Dict(dict) => dict.get(field).is_some_and(|found| canonical_json(found) == value),
+        _ => false,
+    })
+}
+
+fn canonical_json(value: &Value) -> String {
+    serde_json::to_string(&value_to_json(value)).unwrap()
+}
+
+mod float_repr {
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub(super) fn serialize<S: Serializer>(f: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        match *f {
+            f if !serializer.is_human_readable() || f.is_finite() => serializer.serialize_f64(f),
+            f if f.is_nan() => serializer.serialize_str("NaN"),
+            f if f > 0.0 => serializer.serialize_str("inf"),
+            _ => serializer.serialize_str("-inf"),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(FloatVisitor)
+        } else {
+            deserializer.deserialize_f64(FloatVisitor)
+        }
+    }
+
+    struct FloatVisitor;
+
+    impl Visitor<'_> for FloatVisitor {
+        type Value = f64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a number, \"NaN\", \"inf\" or \"-inf\"")
+        }
+
+        fn visit_f64<E: de::Error>(self, f: f64) -> Result<f64, E> {
+            Ok(f)
+        }
+
+        fn visit_i64<E: de::Error>(self, i: i64) -> Result<f64, E> {
+            Ok(i as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, u: u64) -> Result<f64, E> {
+            Ok(u as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<f64, E> {
+            match s {
+                "NaN" => Ok(f64::NAN),
+                "inf" => Ok(f64::INFINITY),
+                "-inf" => Ok(f64::NEG_INFINITY),
+                _ => Err(E::invalid_value(de::Unexpected::Str(s), &self)),
+            }
+        }
+    }
+}
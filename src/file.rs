@@ -0,0 +1,130 @@
+use crate::diff::{DeepDiff, Diff, Value};
+use memmap2::Mmap;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            FileError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(_, err) => Some(err),
+            FileError::Parse(_, err) => Some(err),
+        }
+    }
+}
+
+impl DeepDiff {
+    /// Compares two JSON files. Each is memory-mapped and parsed straight
+    /// into a `Value`, so neither the raw text nor an intermediate
+    /// `serde_json::Value` is held in memory alongside the trees.
+    pub fn compare_files(&self, path1: &Path, path2: &Path) -> Result<Vec<Diff>, FileError> {
+        let map1 = map_file(path1)?;
+        let map2 = map_file(path2)?;
+
+        if self.has_string_fields() {
+            let json1: JsonValue = parse(&map1, path1)?;
+            let json2: JsonValue = parse(&map2, path2)?;
+            return Ok(self.compare_json(&json1, &json2));
+        }
+
+        let JsonDocument(v1) = parse(&map1, path1)?;
+        drop(map1);
+        let JsonDocument(v2) = parse(&map2, path2)?;
+        drop(map2);
+        Ok(self.compare(&v1, &v2))
+    }
+}
+
+fn map_file(path: &Path) -> Result<Mmap, FileError> {
+    let file = File::open(path).map_err(|err| FileError::Io(path.to_path_buf(), err))?;
+    // Safety: the map is only read while parsing; a file truncated by another
+    // process meanwhile is outside what this API guards against.
+    unsafe { Mmap::map(&file) }.map_err(|err| FileError::Io(path.to_path_buf(), err))
+}
+
+fn parse<'a, T: Deserialize<'a>>(bytes: &'a [u8], path: &Path) -> Result<T, FileError> {
+    serde_json::from_slice(bytes).map_err(|err| FileError::Parse(path.to_path_buf(), err))
+}
+
+// A JSON document deserialized directly into a `Value`, converting numbers
+// the same way as `json_to_value`.
+struct JsonDocument(Value);
+
+impl<'de> Deserialize<'de> for JsonDocument {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor).map(JsonDocument)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Int(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(i64::try_from(n).map_or(Value::UInt(n), Value::Int))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Value::Float(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut arr = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(JsonDocument(value)) = seq.next_element()? {
+            arr.push(value);
+        }
+        Ok(Value::Array(arr))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut dict = BTreeMap::new();
+        while let Some((key, JsonDocument(value))) = map.next_entry::<String, JsonDocument>()? {
+            dict.insert(key, value);
+        }
+        Ok(Value::Dict(dict))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::diff::{diffs_to_json, to_json_patch, DeepDiff, Diff, Value};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DiffStats {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub total: usize,
+}
+
+/// Tallies `diffs` by kind. Summaries count the diffs they stand for, and
+/// everything that isn't an addition or removal counts as a change.
+pub fn summary(diffs: &[Diff]) -> DiffStats {
+    let mut stats = DiffStats::default();
+    for diff in diffs {
+        match diff {
+            Diff::Added(..) => stats.added += 1,
+            Diff::Removed(..) => stats.removed += 1,
+            Diff::Summarized(_, summary) => {
+                stats.added += summary.added;
+                stats.removed += summary.removed;
+                stats.changed += summary.changed;
+            }
+            _ => stats.changed += 1,
+        }
+    }
+    stats.total = stats.added + stats.removed + stats.changed;
+    stats
+}
+
+/// The result of one comparison, renderable in each supported output format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FullDiff {
+    diffs: Vec<Diff>,
+    float_precision: Option<u32>,
+}
+
+impl FullDiff {
+    pub fn diffs(&self) -> &[Diff] {
+        &self.diffs
+    }
+
+    pub fn into_diffs(self) -> Vec<Diff> {
+        self.diffs
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        match self.float_precision {
+            Some(decimals) => diffs_to_json(&self.diffs.iter().map(|diff| diff.rounded(decimals)).collect::<Vec<_>>()),
+            None => diffs_to_json(&self.diffs),
+        }
+    }
+
+    pub fn to_human(&self) -> String {
+        self.diffs
+            .iter()
+            .map(|diff| match self.float_precision {
+                Some(decimals) => diff.to_log_line_with_precision(decimals),
+                None => diff.to_log_line(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_json_patch(&self) -> JsonValue {
+        to_json_patch(&self.diffs)
+    }
+
+    pub fn stats(&self) -> DiffStats {
+        summary(&self.diffs)
+    }
+}
+
+impl DeepDiff {
+    pub fn compare_full(&self, v1: &Value, v2: &Value) -> FullDiff {
+        FullDiff {
+            diffs: self.compare(v1, v2),
+            float_precision: self.output_precision(),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::diff::{DeepDiff, Diff, Value};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The span that differs between two strings, in extended grapheme
+/// clusters, so an emoji sequence or a letter with combining marks is never
+/// split. `start` counts the clusters the strings share before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphemeEdit {
+    pub start: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl GraphemeEdit {
+    pub fn between(old: &str, new: &str) -> Self {
+        let old: Vec<&str> = old.graphemes(true).collect();
+        let new: Vec<&str> = new.graphemes(true).collect();
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        GraphemeEdit {
+            start: prefix,
+            removed: old[prefix..old.len() - suffix].concat(),
+            inserted: new[prefix..new.len() - suffix].concat(),
+        }
+    }
+}
+
+/// A diff with, for a change between two strings, the edit that makes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextDiff {
+    pub diff: Diff,
+    pub edit: Option<GraphemeEdit>,
+}
+
+impl DeepDiff {
+    pub fn compare_text(&self, v1: &Value, v2: &Value) -> Vec<TextDiff> {
+        self.compare(v1, v2)
+            .into_iter()
+            .map(|diff| TextDiff {
+                edit: match &diff {
+                    Diff::Changed(_, Value::String(old), Value::String(new)) => {
+                        Some(GraphemeEdit::between(old, new))
+                    }
+                    _ => None,
+                },
+                diff,
+            })
+            .collect()
+    }
+}
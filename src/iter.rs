@@ -0,0 +1,69 @@
+use crate::diff::{DeepDiff, Diff, DictEntry, Value};
+use std::rc::Rc;
+
+enum Frame<'a> {
+    // Two values still to compare.
+    Values(&'a Value, &'a Value, String),
+    // The remaining entries of a pair of dicts, under their shared path.
+    Entries(Box<dyn Iterator<Item = DictEntry<'a>> + 'a>, Rc<String>),
+}
+
+// Walks the documents with an explicit stack, descending into dicts one
+// entry at a time. Arrays, and dicts that other options report as a whole,
+// are compared in one step.
+pub(crate) struct DiffIter<'a> {
+    deep_diff: &'a DeepDiff,
+    stack: Vec<Frame<'a>>,
+    ready: std::vec::IntoIter<Diff>,
+}
+
+impl Iterator for DiffIter<'_> {
+    type Item = Diff;
+
+    fn next(&mut self) -> Option<Diff> {
+        loop {
+            if let Some(diff) = self.ready.next() {
+                return Some(diff);
+            }
+
+            let mut found = Vec::new();
+            match self.stack.pop()? {
+                Frame::Values(v1, v2, path) => self.expand(v1, v2, path, &mut found),
+                Frame::Entries(mut entries, path) => {
+                    if let Some((key, value1, value2)) = entries.next() {
+                        self.stack.push(Frame::Entries(entries, path.clone()));
+                        if let Some((v1, v2, path)) = self.deep_diff.visit_entry(key, value1, value2, &path, &mut found) {
+                            self.stack.push(Frame::Values(v1, v2, path));
+                        }
+                    }
+                }
+            }
+            self.ready = found.into_iter();
+        }
+    }
+}
+
+impl<'a> DiffIter<'a> {
+    pub(crate) fn new(deep_diff: &'a DeepDiff, v1: &'a Value, v2: &'a Value) -> Self {
+        DiffIter {
+            deep_diff,
+            stack: vec![Frame::Values(v1, v2, String::new())],
+            ready: Vec::new().into_iter(),
+        }
+    }
+
+    fn expand(&mut self, v1: &'a Value, v2: &'a Value, path: String, found: &mut Vec<Diff>) {
+        let deep_diff = self.deep_diff;
+        let path = match deep_diff.enter_subtree(v1, v2, path, found) {
+            Some(path) => path,
+            None => return,
+        };
+        match (v1, v2) {
+            (Value::Dict(dict1), Value::Dict(dict2)) if !deep_diff.summarizes(dict1, dict2) => {
+                let entries = deep_diff.dict_entries(dict1, dict2, &path);
+                self.stack.push(Frame::Entries(entries, Rc::new(path)));
+            }
+            _ => deep_diff.compare_values(v1, v2, path, found),
+        }
+    }
+}
@@ -1,4 +1,46 @@
+mod apply;
+mod breaking;
+mod cache;
+mod canonical;
+mod classify;
+mod datetime;
 mod diff;
+#[cfg(feature = "mmap")]
+mod file;
+mod full;
+#[cfg(feature = "graphemes")]
+mod grapheme;
+mod hash;
+mod iter;
+mod located;
+mod mutation;
+mod output;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod path;
+mod report;
+mod shared;
+mod sink;
+#[cfg(feature = "yaml")]
+mod yaml;
+#[cfg(test)]
 mod test;
 
-pub use diff::{DeepDiff, Diff, diffs_to_json};
\ No newline at end of file
+pub use apply::{ApplyError, IdempotentOp, apply, apply_idempotent, to_idempotent_patch};
+pub use breaking::{AnnotatedDiff, BreakingRules};
+pub use classify::{ChangeClass, classify_changes};
+pub use canonical::to_canonical_bytes;
+pub use diff::{ArrayAlgo, CappedDiffs, DeepDiff, Diff, DiffMetrics, DiffSummary, PathFormat, Value, changed_path_signature, changed_top_level_keys, chunk_diffs, common_prefix_depth, diffs_to_json, group_by_array_element, invert, key_diff, patch_id, to_json_patch, to_merge_patch_with_removals, to_merge_patch_with_removals_with_source, to_update_payload, to_update_payload_with_source, upsert_diffs};
+#[cfg(feature = "yaml")]
+pub use diff::diffs_to_yaml;
+#[cfg(feature = "mmap")]
+pub use file::FileError;
+pub use full::{DiffStats, FullDiff, summary};
+#[cfg(feature = "graphemes")]
+pub use grapheme::{GraphemeEdit, TextDiff};
+pub use located::{LocatedDiff, Position};
+pub use mutation::{Mutation, to_mutations, to_mutations_with_source};
+pub use output::{JsonOptions, diffs_to_json_with};
+pub use path::{PathError, PathSegment};
+pub use report::{Addition, ChangeReport, Modification, Removal};
+pub use shared::SharedValue;
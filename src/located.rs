@@ -0,0 +1,182 @@
+use crate::diff::{index_path, key_path, DeepDiff, Diff};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A 1-based line and column, counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A diff with where its old and new values start in the source texts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocatedDiff {
+    pub diff: Diff,
+    pub old: Option<Position>,
+    pub new: Option<Position>,
+}
+
+impl DeepDiff {
+    /// Parses and compares two JSON texts, locating each diff's values in
+    /// them by path. Options that report paths not found in the input, such
+    /// as `canonicalize_array_order` or `unwrap_path`, leave positions unset.
+    pub fn compare_json_str_located(
+        &self,
+        s1: &str,
+        s2: &str,
+    ) -> Result<Vec<LocatedDiff>, serde_json::Error> {
+        let json1: JsonValue = serde_json::from_str(s1)?;
+        let json2: JsonValue = serde_json::from_str(s2)?;
+        let positions1 = self.value_positions(s1);
+        let positions2 = self.value_positions(s2);
+
+        Ok(self
+            .compare_json(&json1, &json2)
+            .into_iter()
+            .map(|diff| LocatedDiff {
+                old: match diff {
+                    Diff::Added(..) => None,
+                    _ => positions1.get(diff.path()).copied(),
+                },
+                new: match diff {
+                    Diff::Removed(..) => None,
+                    _ => positions2.get(diff.path()).copied(),
+                },
+                diff,
+            })
+            .collect())
+    }
+
+    fn value_positions(&self, text: &str) -> HashMap<String, Position> {
+        let mut scanner = Scanner {
+            chars: text.chars().peekable(),
+            position: Position { line: 1, column: 1 },
+            positions: HashMap::new(),
+        };
+        scanner.value(String::new());
+        scanner
+            .positions
+            .into_iter()
+            .map(|(path, position)| (self.output_path(path), position))
+            .collect()
+    }
+}
+
+// Walks text already validated by serde_json, recording where each value
+// starts under the same paths the comparison reports.
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: Position,
+    positions: HashMap<String, Position>,
+}
+
+impl Scanner<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self, path: String) {
+        self.skip_whitespace();
+        self.positions.insert(path.clone(), self.position);
+        match self.chars.peek() {
+            Some('{') => {
+                self.bump();
+                self.skip_whitespace();
+                while self.chars.peek() == Some(&'"') {
+                    let key = self.string();
+                    self.skip_whitespace();
+                    self.bump();
+                    self.value(key_path(&path, &key));
+                    self.skip_whitespace();
+                    if self.chars.peek() == Some(&',') {
+                        self.bump();
+                        self.skip_whitespace();
+                    }
+                }
+                self.bump();
+            }
+            Some('[') => {
+                self.bump();
+                self.skip_whitespace();
+                let mut index = 0;
+                while self.chars.peek().is_some_and(|c| *c != ']') {
+                    self.value(index_path(&path, index));
+                    index += 1;
+                    self.skip_whitespace();
+                    if self.chars.peek() == Some(&',') {
+                        self.bump();
+                    }
+                }
+                self.bump();
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while self
+                    .chars
+                    .peek()
+                    .is_some_and(|c| !matches!(c, ',' | ']' | '}') && !c.is_ascii_whitespace())
+                {
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut out = String::new();
+        self.bump();
+        while let Some(c) = self.bump() {
+            match c {
+                '"' => break,
+                '\\' => match self.bump() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => {
+                        let mut units = vec![self.hex4()];
+                        if (0xD800..0xDC00).contains(&units[0]) && self.chars.peek() == Some(&'\\')
+                        {
+                            self.bump();
+                            self.bump();
+                            units.push(self.hex4());
+                        }
+                        out.extend(
+                            char::decode_utf16(units)
+                                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                        );
+                    }
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    fn hex4(&mut self) -> u16 {
+        (0..4).fold(0, |acc, _| {
+            acc * 16 + self.bump().and_then(|c| c.to_digit(16)).unwrap_or(0) as u16
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use crate::apply::lookup;
+use crate::diff::{Diff, Value};
+use crate::path::{parse_path, PathSegment};
+
+/// A database-style write derived from a diff. Paths are structured so they
+/// can be mapped onto columns and nested documents directly.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    Set { path: Vec<PathSegment>, value: Value },
+    Delete { path: Vec<PathSegment> },
+    Append { path: Vec<PathSegment>, value: Value },
+    /// Inserts into an array at the index `path` ends with, shifting the
+    /// elements from there on.
+    Insert { path: Vec<PathSegment>, value: Value },
+}
+
+/// Maps diffs to mutations: added array elements become `Insert` at their
+/// index, or `Append` on the array when matched by key, other additions and
+/// changes become `Set`, and removals `Delete`. Summaries and reorders carry
+/// no values to write and are skipped.
+///
+/// The diffs don't tell whether an index was past the end of the old array;
+/// `to_mutations_with_source` appends the elements that were.
+pub fn to_mutations(diffs: &[Diff]) -> Vec<Mutation> {
+    mutations(diffs, |_, _| false)
+}
+
+/// `to_mutations`, with elements added at or past the end of their array in
+/// `base`, the value the diffs were computed from, as `Append`.
+pub fn to_mutations_with_source(diffs: &[Diff], base: &Value) -> Vec<Mutation> {
+    mutations(diffs, |array, index| {
+        matches!(lookup(base, array), Some(Value::Array(arr)) if index >= arr.len())
+    })
+}
+
+fn mutations(diffs: &[Diff], past_end: impl Fn(&[PathSegment], usize) -> bool) -> Vec<Mutation> {
+    let mut mutations = Vec::new();
+
+    for diff in diffs {
+        match diff {
+            Diff::Added(path, value) => {
+                let mut path = parse_path(path);
+                match path.last() {
+                    Some(&PathSegment::Index(index)) if !past_end(&path[..path.len() - 1], index) => {
+                        mutations.push(Mutation::Insert {
+                            path,
+                            value: value.clone(),
+                        });
+                    }
+                    Some(PathSegment::Index(_) | PathSegment::Keyed(..)) => {
+                        path.pop();
+                        mutations.push(Mutation::Append {
+                            path,
+                            value: value.clone(),
+                        });
+                    }
+                    _ => mutations.push(Mutation::Set {
+                        path,
+                        value: value.clone(),
+                    }),
+                }
+            }
+            Diff::Removed(path, _) => mutations.push(Mutation::Delete {
+                path: parse_path(path),
+            }),
+            Diff::Changed(path, _, value)
+            | Diff::StructureChanged(path, _, value)
+            | Diff::TypeChanged(path, _, value)
+            | Diff::NumericChanged(path, _, value, _) => mutations.push(Mutation::Set {
+                path: parse_path(path),
+                value: value.clone(),
+            }),
+            Diff::Summarized(..) | Diff::Reordered(_) => {}
+        }
+    }
+
+    mutations
+}
@@ -0,0 +1,98 @@
+use crate::diff::{diffs_to_json, round_floats, value_to_json, DeepDiff, Diff, Value};
+use crate::path::{parse_path, PathSegment};
+use serde_json::{Map, Value as JsonValue};
+
+/// Extra fields to include when rendering diffs with `diffs_to_json_with`.
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    pub type_names: bool,
+    /// Adds `segments`, the dotted path split into keys (strings) and
+    /// indices (numbers), e.g. `["b", 1, "age"]`. Elements matched by
+    /// `array_key` appear as `{"id": 2}`.
+    pub segments: bool,
+    /// Rounds float values to this many decimal places.
+    pub float_precision: Option<u32>,
+}
+
+impl DeepDiff {
+    /// `diffs_to_json` with floats rounded to `float_output_precision`.
+    pub fn diffs_to_json(&self, diffs: &[Diff]) -> JsonValue {
+        match self.output_precision() {
+            Some(decimals) => diffs_to_json(&diffs.iter().map(|diff| diff.rounded(decimals)).collect::<Vec<_>>()),
+            None => diffs_to_json(diffs),
+        }
+    }
+
+    /// Options for `diffs_to_json_with` that round floats to
+    /// `float_output_precision`.
+    pub fn json_options(&self) -> JsonOptions {
+        JsonOptions { float_precision: self.output_precision(), ..JsonOptions::default() }
+    }
+}
+
+/// Renders each diff as a flat object (`kind`, `path`, `old`, `new`) with
+/// plain JSON values, plus whatever `options` asks for.
+pub fn diffs_to_json_with(diffs: &[Diff], options: &JsonOptions) -> JsonValue {
+    JsonValue::Array(diffs.iter().map(|diff| diff_to_json(diff, options)).collect())
+}
+
+fn diff_to_json(diff: &Diff, options: &JsonOptions) -> JsonValue {
+    let mut object = Map::new();
+    object.insert("kind".to_string(), JsonValue::from(diff.kind()));
+    object.insert("path".to_string(), JsonValue::from(diff.path()));
+    if options.segments {
+        object.insert("segments".to_string(), segments_to_json(diff.path()));
+    }
+
+    match diff {
+        Diff::Added(_, new) => insert_value(&mut object, "new", new, options),
+        Diff::Removed(_, old) => insert_value(&mut object, "old", old, options),
+        Diff::Changed(_, old, new) | Diff::StructureChanged(_, old, new) | Diff::TypeChanged(_, old, new) => {
+            insert_value(&mut object, "old", old, options);
+            insert_value(&mut object, "new", new, options);
+        }
+        Diff::NumericChanged(_, old, new, percent) => {
+            insert_value(&mut object, "old", old, options);
+            insert_value(&mut object, "new", new, options);
+            object.insert("percent".to_string(), JsonValue::from(*percent));
+        }
+        Diff::Summarized(_, summary) => {
+            object.insert("added".to_string(), JsonValue::from(summary.added));
+            object.insert("removed".to_string(), JsonValue::from(summary.removed));
+            object.insert("changed".to_string(), JsonValue::from(summary.changed));
+            object.insert("sample".to_string(), diffs_to_json_with(&summary.sample, options));
+        }
+        Diff::Reordered(_) => {}
+    }
+
+    JsonValue::Object(object)
+}
+
+fn segments_to_json(path: &str) -> JsonValue {
+    parse_path(path)
+        .into_iter()
+        .map(|segment| match segment {
+            PathSegment::Key(key) => JsonValue::from(key),
+            PathSegment::Index(index) => JsonValue::from(index),
+            PathSegment::Keyed(field, value) => {
+                let value = serde_json::from_str(&value).unwrap_or(JsonValue::Null);
+                JsonValue::Object(Map::from_iter([(field, value)]))
+            }
+        })
+        .collect()
+}
+
+fn insert_value(object: &mut Map<String, JsonValue>, field: &str, value: &Value, options: &JsonOptions) {
+    let json = match options.float_precision {
+        Some(decimals) => {
+            let mut value = value.clone();
+            round_floats(&mut value, decimals);
+            value_to_json(&value)
+        }
+        None => value_to_json(value),
+    };
+    object.insert(field.to_string(), json);
+    if options.type_names {
+        object.insert(format!("{}_type", field), JsonValue::from(value.type_name()));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::diff::{DeepDiff, Diff, DictEntry};
+use crate::sink::DiffSink;
+use rayon::prelude::*;
+
+// Dicts smaller than this aren't worth splitting across threads.
+pub(crate) const MIN_PARALLEL_ENTRIES: usize = 256;
+
+impl DeepDiff {
+    // Compares each entry on the rayon pool, then replays what each one
+    // produced into `out` in entry order, so the result matches a sequential
+    // run. Only the early exit of a full sink is lost.
+    pub(crate) fn compare_entries_parallel(&self, entries: Vec<DictEntry<'_>>, path: &str, out: &mut dyn DiffSink) {
+        let probing = out.probing();
+        let recorded: Vec<Recorded> = entries
+            .into_par_iter()
+            .map(|(key, value1, value2)| {
+                let mut recorded = Recorded { events: Vec::new(), probing };
+                self.compare_entry(key, value1, value2, path, &mut recorded);
+                recorded
+            })
+            .collect();
+
+        for events in recorded {
+            if out.is_full() {
+                return;
+            }
+            events.replay(out);
+        }
+    }
+}
+
+enum Event {
+    Push(Diff),
+    Enter,
+    Tolerated(String),
+}
+
+// Everything a traversal told its sink, kept to be replayed later. Probes
+// stay probes on the worker threads.
+struct Recorded {
+    events: Vec<Event>,
+    probing: bool,
+}
+
+impl Recorded {
+    fn replay(self, out: &mut dyn DiffSink) {
+        for event in self.events {
+            match event {
+                Event::Push(diff) => out.push(diff),
+                Event::Enter => out.enter(),
+                Event::Tolerated(path) => out.tolerated(&path),
+            }
+        }
+    }
+}
+
+impl DiffSink for Recorded {
+    fn push(&mut self, diff: Diff) {
+        self.events.push(Event::Push(diff));
+    }
+
+    fn enter(&mut self) {
+        self.events.push(Event::Enter);
+    }
+
+    fn tolerated(&mut self, path: &str) {
+        self.events.push(Event::Tolerated(path.to_string()));
+    }
+
+    fn probing(&self) -> bool {
+        self.probing
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+    /// An array element picked out by `array_key`: the field and its value
+    /// as compact JSON, written `[id=2]`.
+    Keyed(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    NotFound(String),
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::NotFound(path) => write!(f, "no value at path `{}`", path),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Splits a diff path into segments. The grammar is:
+///
+/// ```text
+/// path    := ( key | bracket )? ( "." key | bracket )*
+/// bracket := "[" digits "]" | "[\"" quoted "\"]" | "[" field "=" json "]"
+/// ```
+///
+/// Keys containing `.`, `[` or `]` are written in the quoted form, with `"`
+/// and `\` escaped by a backslash, e.g. `root["a.b"]`. So is the empty key,
+/// as `[""]`, which would otherwise read as the root or a trailing `.`.
+pub fn parse_path(path: &str) -> Vec<PathSegment> {
+    scan(path, false)
+        .into_iter()
+        .filter_map(|(segment, _)| match segment {
+            Raw::Key(key) | Raw::Quoted(key) => Some(PathSegment::Key(key)),
+            Raw::Index(index) => Some(PathSegment::Index(index)),
+            Raw::Keyed(field, value) => Some(PathSegment::Keyed(field, value)),
+            Raw::AnyIndex => None,
+        })
+        .collect()
+}
+
+/// Appends `key` to `path`, quoting it when it would otherwise be ambiguous.
+pub(crate) fn push_key(path: &mut String, key: &str) {
+    push_key_joined(path, key, ".");
+}
+
+// `push_key` with keys joined by `separator`.
+fn push_key_joined(path: &mut String, key: &str, separator: &str) {
+    if key.is_empty() || key.contains(separator) || key.contains(['[', ']']) {
+        path.push_str("[\"");
+        for c in key.chars() {
+            if c == '"' || c == '\\' {
+                path.push('\\');
+            }
+            path.push(c);
+        }
+        path.push_str("\"]");
+    } else {
+        if !path.is_empty() {
+            path.push_str(separator);
+        }
+        path.push_str(key);
+    }
+}
+
+// Rewrites a dotted path with keys joined by `separator`.
+pub(crate) fn with_separator(path: &str, separator: &str) -> String {
+    let mut joined = String::new();
+    for segment in parse_path(path) {
+        match segment {
+            PathSegment::Key(key) => push_key_joined(&mut joined, &key, separator),
+            PathSegment::Index(index) => {
+                joined.push('[');
+                joined.push_str(&index.to_string());
+                joined.push(']');
+            }
+            PathSegment::Keyed(field, value) => {
+                joined.push_str(&format!("[{}={}]", field, value));
+            }
+        }
+    }
+    joined
+}
+
+enum Raw {
+    Key(String),
+    // A `["key"]` segment, never read as a wildcard.
+    Quoted(String),
+    Index(usize),
+    Keyed(String, String),
+    AnyIndex,
+}
+
+// Parses `path` into segments, each with the byte offset where it ends. With
+// `wildcards`, `[*]` is accepted as an index.
+fn scan(path: &str, wildcards: bool) -> Vec<(Raw, usize)> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    let mut expect_key = !path.is_empty();
+
+    while expect_key || pos < path.len() {
+        let rest = &path[pos..];
+        if let Some((segment, len)) = bracket_segment(rest, wildcards) {
+            segments.push((segment, pos + len));
+            pos += len;
+            expect_key = false;
+        } else if expect_key || rest.starts_with('.') {
+            let key_start = if expect_key { pos } else { pos + 1 };
+            let key_end = key_start + key_len(&path[key_start..], wildcards);
+            segments.push((Raw::Key(path[key_start..key_end].to_string()), key_end));
+            pos = key_end;
+            expect_key = false;
+        } else {
+            // Text straight after a bracket, as in `a[0]b`; read it as a key.
+            expect_key = true;
+        }
+    }
+
+    segments
+}
+
+// The number of segments in `path`, as `parse_path(path).len()` without
+// building them.
+pub(crate) fn path_depth(path: &str) -> usize {
+    let mut depth = 0;
+    let mut pos = 0;
+    let mut expect_key = !path.is_empty();
+
+    while expect_key || pos < path.len() {
+        let rest = &path[pos..];
+        if let Some((_, len)) = bracket_segment(rest, false) {
+            pos += len;
+        } else if expect_key || rest.starts_with('.') {
+            let key_start = if expect_key { pos } else { pos + 1 };
+            pos = key_start + key_len(&path[key_start..], false);
+        } else {
+            expect_key = true;
+            continue;
+        }
+        depth += 1;
+        expect_key = false;
+    }
+
+    depth
+}
+
+fn key_len(rest: &str, wildcards: bool) -> usize {
+    rest.char_indices()
+        .find(|&(i, c)| c == '.' || (c == '[' && bracket_segment(&rest[i..], wildcards).is_some()))
+        .map_or(rest.len(), |(i, _)| i)
+}
+
+// Reads a `[n]`, `["key"]` or `[field=json]` segment at the start of `rest`,
+// returning it with its length. Brackets that are none of these stay part of
+// the surrounding key.
+fn bracket_segment(rest: &str, wildcards: bool) -> Option<(Raw, usize)> {
+    if let Some(quoted) = rest.strip_prefix("[\"") {
+        let mut key = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => key.push(chars.next()?.1),
+                '"' if quoted[i + 1..].starts_with(']') => return Some((Raw::Quoted(key), i + 4)),
+                '"' => return None,
+                c => key.push(c),
+            }
+        }
+        return None;
+    }
+
+    if !rest.starts_with('[') {
+        return None;
+    }
+    if let Some(keyed) = keyed_segment(rest) {
+        return Some(keyed);
+    }
+    let inner = &rest[1..rest.find(']')?];
+    if wildcards && inner == "*" {
+        Some((Raw::AnyIndex, 3))
+    } else if !inner.is_empty() && inner.bytes().all(|b| b.is_ascii_digit()) {
+        Some((Raw::Index(inner.parse().ok()?), inner.len() + 2))
+    } else {
+        None
+    }
+}
+
+// The JSON value may itself contain `]`, so it is read with a JSON parser
+// rather than up to the next bracket.
+fn keyed_segment(rest: &str) -> Option<(Raw, usize)> {
+    let inner = &rest[1..];
+    let equals = inner.find('=')?;
+    let field = &inner[..equals];
+    if field.is_empty() || field.contains(['[', ']', '"']) {
+        return None;
+    }
+    let json = &inner[equals + 1..];
+    let mut values = serde_json::Deserializer::from_str(json).into_iter::<serde_json::Value>();
+    let value = values.next()?.ok()?;
+    let end = values.byte_offset();
+    if !json[end..].starts_with(']') {
+        return None;
+    }
+    let len = 1 + equals + 1 + end + 1;
+    Some((Raw::Keyed(field.to_string(), value.to_string()), len))
+}
+
+pub fn is_under(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.is_empty() || rest.is_empty() || rest.starts_with('.') || rest.starts_with('['),
+        None => false,
+    }
+}
+
+// A path with wildcards: `*` or `[*]` matches any one segment and `**` any
+// run of segments, e.g. `users[*].id` or `**.id`.
+#[derive(Debug, Clone)]
+pub(crate) struct PathPattern(Vec<PatternSegment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum PatternSegment {
+    Exact(PathSegment),
+    Any,
+    AnyRun,
+}
+
+impl PathPattern {
+    pub(crate) fn new(pattern: &str) -> Self {
+        let segments = scan(pattern, true)
+            .into_iter()
+            .map(|(segment, _)| match segment {
+                Raw::Key(key) if key == "**" => PatternSegment::AnyRun,
+                Raw::Key(key) if key == "*" => PatternSegment::Any,
+                Raw::Key(key) | Raw::Quoted(key) => PatternSegment::Exact(PathSegment::Key(key)),
+                Raw::Index(index) => PatternSegment::Exact(PathSegment::Index(index)),
+                Raw::Keyed(field, value) => PatternSegment::Exact(PathSegment::Keyed(field, value)),
+                Raw::AnyIndex => PatternSegment::Any,
+            })
+            .collect();
+        PathPattern(segments)
+    }
+
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        matches_segments(&self.0, &parse_path(path))
+    }
+
+    // Whether `path` or one of its ancestors matches.
+    pub(crate) fn covers(&self, path: &[PathSegment]) -> bool {
+        covers_segments(&self.0, path)
+    }
+
+    // Whether some path below `path` could match.
+    pub(crate) fn leads_to(&self, path: &[PathSegment]) -> bool {
+        leads_to_segments(&self.0, path)
+    }
+}
+
+fn matches_segments(pattern: &[PatternSegment], path: &[PathSegment]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((PatternSegment::AnyRun, rest)) => (0..=path.len()).any(|skip| matches_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((first, path_rest)) => {
+                (*segment == PatternSegment::Any || *segment == PatternSegment::Exact(first.clone()))
+                    && matches_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn covers_segments(pattern: &[PatternSegment], path: &[PathSegment]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((PatternSegment::AnyRun, rest)) => (0..=path.len()).any(|skip| covers_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((first, path_rest)) => segment_matches(segment, first) && covers_segments(rest, path_rest),
+            None => false,
+        },
+    }
+}
+
+fn leads_to_segments(pattern: &[PatternSegment], path: &[PathSegment]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some((PatternSegment::AnyRun, rest)), Some((_, path_rest))) => {
+            leads_to_segments(rest, path) || leads_to_segments(pattern, path_rest)
+        }
+        (Some((segment, rest)), Some((first, path_rest))) => {
+            segment_matches(segment, first) && leads_to_segments(rest, path_rest)
+        }
+    }
+}
+
+fn segment_matches(segment: &PatternSegment, path_segment: &PathSegment) -> bool {
+    match segment {
+        PatternSegment::Exact(exact) => exact == path_segment,
+        PatternSegment::Any | PatternSegment::AnyRun => true,
+    }
+}
+
+// Returns the prefix of `path` ending at its deepest array element, if any.
+pub fn element_prefix(path: &str) -> Option<&str> {
+    scan(path, false)
+        .into_iter()
+        .rev()
+        .find(|(segment, _)| matches!(segment, Raw::Index(_) | Raw::Keyed(..)))
+        .map(|(_, end)| &path[..end])
+}
@@ -0,0 +1,64 @@
+use crate::diff::{DeepDiff, Diff, Value};
+use crate::path::{parse_path, PathSegment};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Addition {
+    pub path: Vec<PathSegment>,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Removal {
+    pub path: Vec<PathSegment>,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modification {
+    pub path: Vec<PathSegment>,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeReport {
+    pub additions: Vec<Addition>,
+    pub removals: Vec<Removal>,
+    pub modifications: Vec<Modification>,
+}
+
+impl ChangeReport {
+    pub fn from_diffs(diffs: Vec<Diff>) -> Self {
+        let mut report = ChangeReport::default();
+
+        for diff in diffs {
+            match diff {
+                Diff::Added(path, value) => report.additions.push(Addition {
+                    path: parse_path(&path),
+                    value,
+                }),
+                Diff::Removed(path, value) => report.removals.push(Removal {
+                    path: parse_path(&path),
+                    value,
+                }),
+                Diff::Changed(path, old, new)
+                | Diff::StructureChanged(path, old, new)
+                | Diff::TypeChanged(path, old, new)
+                | Diff::NumericChanged(path, old, new, _) => report.modifications.push(Modification {
+                    path: parse_path(&path),
+                    old,
+                    new,
+                }),
+                Diff::Summarized(..) | Diff::Reordered(_) => {}
+            }
+        }
+
+        report
+    }
+}
+
+impl DeepDiff {
+    pub fn structured_report(&self, v1: &Value, v2: &Value) -> ChangeReport {
+        ChangeReport::from_diffs(self.compare(v1, v2))
+    }
+}
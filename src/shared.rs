@@ -0,0 +1,96 @@
+use crate::diff::{key_path, DeepDiff, Diff, Value};
+use crate::sink::DiffSink;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A `Value` whose dicts hold their entries behind `Arc`s, so a new version
+/// of a document can reuse the unchanged subtrees of the previous one.
+/// `DeepDiff::compare_shared` skips subtrees both sides point to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedValue {
+    Dict(BTreeMap<String, Arc<SharedValue>>),
+    /// Any value that isn't a dict, held as is.
+    Value(Value),
+}
+
+impl SharedValue {
+    /// The plain value, copying every shared subtree.
+    pub fn to_value(&self) -> Value {
+        match self {
+            SharedValue::Dict(dict) => Value::Dict(
+                dict.iter()
+                    .map(|(key, value)| (key.clone(), value.to_value()))
+                    .collect(),
+            ),
+            SharedValue::Value(value) => value.clone(),
+        }
+    }
+}
+
+impl From<Value> for SharedValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Dict(dict) => SharedValue::Dict(
+                dict.into_iter()
+                    .map(|(key, value)| (key, Arc::new(SharedValue::from(value))))
+                    .collect(),
+            ),
+            value => SharedValue::Value(value),
+        }
+    }
+}
+
+impl DeepDiff {
+    /// Compares two shared values, skipping without a traversal every
+    /// subtree the two sides hold through the same `Arc`. Returns the same
+    /// diffs as `compare` on the plain values. Options that look at a whole
+    /// dict at once, such as `case_insensitive_keys`, `rollup_subtrees` or
+    /// `max_depth`, compare the plain values instead.
+    pub fn compare_shared(&self, v1: &Arc<SharedValue>, v2: &Arc<SharedValue>) -> Vec<Diff> {
+        self.collect(&|out| self.compare_shared_from(v1, v2, String::new(), out), |_| true, None)
+            .0
+    }
+
+    fn compare_shared_from(&self, v1: &Arc<SharedValue>, v2: &Arc<SharedValue>, path: String, out: &mut dyn DiffSink) {
+        if Arc::ptr_eq(v1, v2) && self.skips_equal() {
+            out.enter();
+            return;
+        }
+        let (SharedValue::Dict(dict1), SharedValue::Dict(dict2)) = (&**v1, &**v2) else {
+            return self.compare_recursive(&v1.to_value(), &v2.to_value(), path, out);
+        };
+        if !self.compares_dicts_by_key() {
+            return self.compare_recursive(&v1.to_value(), &v2.to_value(), path, out);
+        }
+
+        out.enter();
+        if self.has_path_filters() && !out.probing() && !self.may_report_below(&path) {
+            return;
+        }
+
+        // Same order as `compare`: the keys of the first dict, then those
+        // only in the second.
+        let shared = dict1.iter().map(|(key, value1)| (key, Some(value1), dict2.get(key)));
+        let added = dict2
+            .iter()
+            .filter(|(key, _)| !dict1.contains_key(*key))
+            .map(|(key, value2)| (key, None, Some(value2)));
+        for (key, value1, value2) in shared.chain(added) {
+            if out.is_full() {
+                return;
+            }
+            match (value1, value2) {
+                (Some(value1), Some(value2)) if !self.ignores_key(key) => {
+                    self.compare_shared_from(value1, value2, key_path(&path, key), out)
+                }
+                _ => self.compare_entry(
+                    key,
+                    value1.map(|value| value.to_value()).as_ref(),
+                    value2.map(|value| value.to_value()).as_ref(),
+                    &path,
+                    out,
+                ),
+            }
+        }
+    }
+}
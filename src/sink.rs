@@ -0,0 +1,328 @@
+use crate::diff::Diff;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::mpsc::{SendError, SyncSender};
+
+pub(crate) trait DiffSink {
+    fn push(&mut self, diff: Diff);
+
+    // Lets the traversal stop early once the sink wants no more diffs.
+    fn is_full(&self) -> bool {
+        false
+    }
+
+    // Called once per pair of nodes the traversal visits.
+    fn enter(&mut self) {}
+
+    // Called for numbers that differ but are equal within tolerance.
+    fn tolerated(&mut self, _path: &str) {}
+
+    // Set by equality probes, which must see differences that path filters
+    // keep out of the output.
+    fn probing(&self) -> bool {
+        false
+    }
+}
+
+impl DiffSink for Vec<Diff> {
+    fn push(&mut self, diff: Diff) {
+        Vec::push(self, diff);
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct First(Option<Diff>);
+
+impl First {
+    pub(crate) fn into_inner(self) -> Option<Diff> {
+        self.0
+    }
+}
+
+impl DiffSink for First {
+    fn push(&mut self, diff: Diff) {
+        if self.0.is_none() {
+            self.0 = Some(diff);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+// Answers whether two values differ at all, stopping at the first diff.
+#[derive(Default)]
+pub(crate) struct Probe(bool);
+
+impl Probe {
+    pub(crate) fn differs(&self) -> bool {
+        self.0
+    }
+}
+
+impl DiffSink for Probe {
+    fn push(&mut self, _diff: Diff) {
+        self.0 = true;
+    }
+
+    fn is_full(&self) -> bool {
+        self.0
+    }
+
+    fn probing(&self) -> bool {
+        true
+    }
+}
+
+// Forwards only the diffs `predicate` accepts.
+pub(crate) struct Filtered<'a, F> {
+    predicate: F,
+    inner: &'a mut dyn DiffSink,
+}
+
+impl<'a, F: Fn(&Diff) -> bool> Filtered<'a, F> {
+    pub(crate) fn new(inner: &'a mut dyn DiffSink, predicate: F) -> Self {
+        Filtered { predicate, inner }
+    }
+}
+
+impl<F: Fn(&Diff) -> bool> DiffSink for Filtered<'_, F> {
+    fn push(&mut self, diff: Diff) {
+        if (self.predicate)(&diff) {
+            self.inner.push(diff);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    fn enter(&mut self) {
+        self.inner.enter();
+    }
+
+    fn tolerated(&mut self, path: &str) {
+        self.inner.tolerated(path);
+    }
+}
+
+// Counts the nodes visited on the way to `inner`.
+pub(crate) struct Counted<'a> {
+    pub(crate) nodes: usize,
+    inner: &'a mut dyn DiffSink,
+}
+
+impl<'a> Counted<'a> {
+    pub(crate) fn new(inner: &'a mut dyn DiffSink) -> Self {
+        Counted { nodes: 0, inner }
+    }
+}
+
+impl DiffSink for Counted<'_> {
+    fn push(&mut self, diff: Diff) {
+        self.inner.push(diff);
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    fn enter(&mut self) {
+        self.nodes += 1;
+    }
+
+    fn tolerated(&mut self, path: &str) {
+        self.inner.tolerated(path);
+    }
+}
+
+// Records the paths of numbers only equal within tolerance on the way to
+// `inner`.
+pub(crate) struct WithTolerated<'a> {
+    inner: &'a mut dyn DiffSink,
+    tolerated: &'a mut Vec<String>,
+}
+
+impl<'a> WithTolerated<'a> {
+    pub(crate) fn new(inner: &'a mut dyn DiffSink, tolerated: &'a mut Vec<String>) -> Self {
+        WithTolerated { inner, tolerated }
+    }
+}
+
+impl DiffSink for WithTolerated<'_> {
+    fn push(&mut self, diff: Diff) {
+        self.inner.push(diff);
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    fn enter(&mut self) {
+        self.inner.enter();
+    }
+
+    fn tolerated(&mut self, path: &str) {
+        self.tolerated.push(path.to_string());
+    }
+}
+
+// Collects at most `max_diffs` diffs, and only while their JSON array stays
+// within `max_bytes`. Past either limit the traversal stops.
+pub(crate) struct Bounded {
+    diffs: Vec<Diff>,
+    max_diffs: usize,
+    max_bytes: Option<usize>,
+    // Bytes of the array so far, counting `[` and one separator per diff.
+    used: usize,
+    truncated: bool,
+}
+
+impl Bounded {
+    pub(crate) fn new(max_diffs: Option<usize>, max_bytes: Option<usize>) -> Self {
+        Bounded {
+            diffs: Vec::new(),
+            max_diffs: max_diffs.unwrap_or(usize::MAX),
+            max_bytes,
+            used: 1,
+            truncated: false,
+        }
+    }
+
+    // The diffs kept, and whether any were dropped.
+    pub(crate) fn into_inner(self) -> (Vec<Diff>, bool) {
+        (self.diffs, self.truncated)
+    }
+}
+
+impl DiffSink for Bounded {
+    fn push(&mut self, diff: Diff) {
+        if self.truncated {
+            return;
+        }
+        if self.diffs.len() == self.max_diffs {
+            self.truncated = true;
+            return;
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            let size = serde_json::to_vec(&diff).map_or(0, |bytes| bytes.len()) + 1;
+            if self.used + size > max_bytes {
+                self.truncated = true;
+                return;
+            }
+            self.used += size;
+        }
+        self.diffs.push(diff);
+    }
+
+    fn is_full(&self) -> bool {
+        self.truncated
+    }
+}
+
+// Sends each diff as it is found, blocking while the channel is full. Stops
+// the traversal once the receiver hangs up.
+pub(crate) struct Channel {
+    tx: SyncSender<Diff>,
+    error: Option<SendError<Diff>>,
+}
+
+impl Channel {
+    pub(crate) fn new(tx: SyncSender<Diff>) -> Self {
+        Channel { tx, error: None }
+    }
+
+    pub(crate) fn finish(self) -> Result<(), SendError<Diff>> {
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+impl DiffSink for Channel {
+    fn push(&mut self, diff: Diff) {
+        if self.error.is_none() {
+            self.error = self.tx.send(diff).err();
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+pub(crate) type DiffScore = dyn Fn(&Diff) -> u64 + Send + Sync;
+
+struct Ranked {
+    score: u64,
+    seq: usize,
+    diff: Diff,
+}
+
+impl Ranked {
+    // Higher scores rank first; on ties the diff seen first wins.
+    fn key(&self) -> (u64, Reverse<usize>) {
+        (self.score, Reverse(self.seq))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+pub(crate) struct TopN<'a> {
+    n: usize,
+    score: &'a DiffScore,
+    heap: BinaryHeap<Reverse<Ranked>>,
+    seq: usize,
+}
+
+impl<'a> TopN<'a> {
+    pub(crate) fn new(n: usize, score: &'a DiffScore) -> Self {
+        TopN {
+            n,
+            score,
+            heap: BinaryHeap::with_capacity(n + 1),
+            seq: 0,
+        }
+    }
+
+    pub(crate) fn into_sorted_vec(self) -> Vec<Diff> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.diff)
+            .collect()
+    }
+}
+
+impl DiffSink for TopN<'_> {
+    fn push(&mut self, diff: Diff) {
+        let ranked = Ranked {
+            score: (self.score)(&diff),
+            seq: self.seq,
+            diff,
+        };
+        self.seq += 1;
+
+        self.heap.push(Reverse(ranked));
+        if self.heap.len() > self.n {
+            self.heap.pop();
+        }
+    }
+}
@@ -4,15 +4,20 @@ use std::fs;
 
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
     use serde_json::json;
-    use crate::diffs_to_json;
+    use crate::{apply, apply_idempotent, to_idempotent_patch, ApplyError, SharedValue};
+    use crate::{classify_changes, ArrayAlgo, BreakingRules, ChangeClass, DiffStats, PathFormat, Position, JsonOptions, Modification, Mutation, PathError, PathSegment};
+    use crate::{changed_path_signature, changed_top_level_keys, chunk_diffs, common_prefix_depth, diffs_to_json, diffs_to_json_with, group_by_array_element, invert, key_diff, patch_id, to_canonical_bytes, to_merge_patch_with_removals, to_merge_patch_with_removals_with_source, to_mutations, to_mutations_with_source, to_update_payload, to_update_payload_with_source, upsert_diffs};
     use super::*;
 
     fn load_json(name: &str) -> JsonValue {
         let content = fs::read_to_string(format!("test_cases/{}.json", name))
-            .expect(&format!("Failed to read file: {}.json", name));
-        serde_json::from_str(&content).expect(&format!("Failed to parse JSON from file: {}.json", name))
+            .unwrap_or_else(|_| panic!("Failed to read file: {}.json", name));
+        serde_json::from_str(&content).unwrap_or_else(|_| panic!("Failed to parse JSON from file: {}.json", name))
     }
 
     #[test]
@@ -54,10 +59,11 @@ mod tests {
         let diffs = deep_diff.compare_json(&json1, &json2);
         println!("{:?}", diffs);
         assert_eq!(diffs.len(), 1);
-        assert!(diffs.contains(&Diff::Added("diff_numbers".to_string(), Value::Int(3))));
+        assert!(diffs.contains(&Diff::Added("diff_numbers[2]".to_string(), Value::Int(3))));
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float_comparison() {
         let json1 = load_json("float_comparison_1");
         let json2 = load_json("float_comparison_2");
@@ -124,4 +130,2268 @@ mod tests {
         assert!(duration < std::time::Duration::from_secs(10));
         assert_eq!(diffs.len(), 5);
     }
+
+    #[test]
+    fn test_log_line_nested_change() {
+        let json1 = json!({"a": {"b": {"x": 1, "y": [1, 2]}}});
+        let json2 = json!({"a": {"b": "replaced"}});
+
+        let deep_diff = DeepDiff::new();
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].to_log_line(), r#"a.b: {"x":1,"y":[1,2]} => "replaced""#);
+        assert_eq!(
+            Diff::Added("c".to_string(), Value::Int(1)).to_log_line(),
+            "c: (none) => 1"
+        );
+    }
+
+    #[test]
+    fn test_bool_string_equivalence() {
+        let json1 = json!({"a": true, "b": true, "c": false, "d": false});
+        let json2 = json!({"a": "true", "b": "FALSE", "c": "True", "d": "false"});
+
+        let strict = DeepDiff::new().compare_json(&json1, &json2);
+        assert_eq!(strict.len(), 4);
+
+        let deep_diff = DeepDiff::new().bool_string_equivalence(true);
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.contains(&Diff::TypeChanged("b".to_string(), Value::Bool(true), Value::String("FALSE".to_string()))));
+        assert!(diffs.contains(&Diff::TypeChanged("c".to_string(), Value::Bool(false), Value::String("True".to_string()))));
+    }
+
+    #[test]
+    fn test_array_alignment_with_insertion() {
+        let a = vec![Value::Int(1), Value::Int(2), Value::Int(3)];
+        let b = vec![Value::Int(1), Value::Int(9), Value::Int(2), Value::Int(3)];
+
+        let deep_diff = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs);
+        let alignment = deep_diff.array_alignment(&a, &b);
+
+        assert_eq!(
+            alignment,
+            vec![(Some(0), Some(0)), (None, Some(1)), (Some(1), Some(2)), (Some(2), Some(3))]
+        );
+        assert_eq!(
+            DeepDiff::new().array_alignment(&a, &b),
+            vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2)), (None, Some(3))]
+        );
+
+        let keyed = DeepDiff::new().array_key("id");
+        let a = keyed.json_to_value(&json!([{"id": 1}, {"id": 2}, {"id": 3}]));
+        let b = keyed.json_to_value(&json!([{"id": 3}, {"id": 4}, {"id": 1, "x": true}]));
+        let (Value::Array(a), Value::Array(b)) = (a, b) else { unreachable!() };
+        assert_eq!(
+            keyed.array_alignment(&a, &b),
+            vec![(Some(0), Some(2)), (Some(1), None), (Some(2), Some(0)), (None, Some(1))]
+        );
+        let canonical = DeepDiff::new().canonicalize_array_order("id");
+        assert_eq!(
+            canonical.array_alignment(&a, &b),
+            vec![(Some(0), Some(2)), (Some(1), Some(0)), (Some(2), Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_value_alias_group() {
+        let json1 = json!({"status": "ACTIVE", "mode": "ACTIVE", "other": "ENABLED"});
+        let json2 = json!({"status": "ENABLED", "mode": "active", "other": "ACTIVE"});
+
+        let deep_diff = DeepDiff::new().value_alias_group(vec![
+            Value::String("ACTIVE".to_string()),
+            Value::String("ENABLED".to_string()),
+        ]);
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 1);
+        assert!(diffs.contains(&Diff::Changed("mode".to_string(), Value::String("ACTIVE".to_string()), Value::String("active".to_string()))));
+    }
+
+    #[test]
+    fn test_update_payload() {
+        let json1 = load_json("nested_1");
+        let mut json2 = load_json("nested_2");
+        json2["c"] = json!(true);
+        json2["a"].as_object_mut().unwrap().remove("x");
+
+        let deep_diff = DeepDiff::new();
+        let diffs = deep_diff.compare_json(&json1, &json2);
+        let payload = to_update_payload(&diffs);
+
+        assert_eq!(payload, json!({"a": {"y": {"z": "deeply nested"}}, "c": true}));
+        assert_eq!(
+            to_update_payload_with_source(&diffs, &deep_diff.json_to_value(&json2)),
+            json!({
+                "a": {"y": {"z": "deeply nested"}},
+                "b": [{"name": "Alice", "age": 31}, {"name": "Charlie", "age": 35}],
+                "c": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_array_identity_composite_key() {
+        let json1 = json!({"records": [
+            {"tenant": "a", "id": 1, "name": "first"},
+            {"tenant": "b", "id": 1, "name": "second"},
+            {"tenant": "a", "id": 2, "name": "third"}
+        ]});
+        let json2 = json!({"records": [
+            {"tenant": "b", "id": 1, "name": "second (renamed)"},
+            {"tenant": "a", "id": 1, "name": "first"},
+            {"tenant": "b", "id": 2, "name": "fourth"}
+        ]});
+
+        let deep_diff = DeepDiff::new().array_identity(Arc::new(|value: &Value| match value {
+            Value::Dict(dict) => Value::Array(vec![dict["tenant"].clone(), dict["id"].clone()]),
+            other => other.clone(),
+        }));
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 3);
+        assert!(diffs.contains(&Diff::Changed("records[1].name".to_string(), Value::String("second".to_string()), Value::String("second (renamed)".to_string()))));
+        assert!(matches!(&diffs[1], Diff::Removed(path, _) if path == "records[2]"));
+        assert!(matches!(&diffs[2], Diff::Added(path, _) if path == "records[2]"));
+    }
+
+    #[test]
+    fn test_rollup_subtrees_anchor() {
+        let json1 = json!({"service": {"name": "api", "config": {"db": {"host": "a", "port": 1}}}});
+        let json2 = json!({"service": {"name": "api", "config": {"db": {"user": "b", "pool": 2}}}});
+
+        let leaf_diffs = DeepDiff::new().compare_json(&json1, &json2);
+        assert_eq!(leaf_diffs.len(), 4);
+
+        let deep_diff = DeepDiff::new().rollup_subtrees(true);
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(&diffs[0], Diff::Changed(path, _, _) if path == "service.config.db"));
+
+        let diffs = deep_diff.compare_json(&json!({"a": {"x": 1}, "b": 2}), &json!({"a": {"x": 3}, "b": 2}));
+        assert_eq!(diffs, vec![Diff::Changed("a.x".to_string(), Value::Int(1), Value::Int(3))]);
+    }
+
+    #[test]
+    fn test_top_n_by_size() {
+        let mut obj1 = serde_json::Map::new();
+        let mut obj2 = serde_json::Map::new();
+        for i in 0..50 {
+            obj1.insert(format!("key{}", i), json!(""));
+            obj2.insert(format!("key{}", i), json!("x".repeat(i)));
+        }
+        let json1 = JsonValue::Object(obj1);
+        let json2 = JsonValue::Object(obj2);
+
+        let deep_diff = DeepDiff::new().top_n_by(5, Arc::new(|diff: &Diff| match diff {
+            Diff::Changed(_, _, Value::String(s)) => s.len() as u64,
+            _ => 0,
+        }));
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        let paths: Vec<_> = diffs
+            .iter()
+            .map(|diff| match diff {
+                Diff::Changed(path, _, _) => path.as_str(),
+                _ => panic!("unexpected diff {:?}", diff),
+            })
+            .collect();
+        assert_eq!(paths, vec!["key49", "key48", "key47", "key46", "key45"]);
+    }
+
+    #[test]
+    fn test_structure_changed() {
+        let json1 = json!({"a": 5, "b": ["x"], "c": {"k": 1}, "d": 1});
+        let json2 = json!({"a": [5], "b": "x", "c": {"k": [1]}, "d": 2});
+
+        let deep_diff = DeepDiff::new();
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 4);
+        assert!(diffs.contains(&Diff::StructureChanged("a".to_string(), Value::Int(5), Value::Array(vec![Value::Int(5)]))));
+        assert!(diffs.contains(&Diff::StructureChanged("b".to_string(), Value::Array(vec![Value::String("x".to_string())]), Value::String("x".to_string()))));
+        assert!(diffs.contains(&Diff::StructureChanged("c.k".to_string(), Value::Int(1), Value::Array(vec![Value::Int(1)]))));
+        assert!(diffs.contains(&Diff::Changed("d".to_string(), Value::Int(1), Value::Int(2))));
+    }
+
+    #[test]
+    fn test_upsert_diffs_under_subtree() {
+        let json1 = load_json("nested_1");
+        let json2 = load_json("nested_2");
+
+        let deep_diff = DeepDiff::new();
+        let mut diffs = deep_diff.compare_json(&json1, &json2);
+        assert_eq!(diffs.len(), 5);
+
+        let updates = vec![Diff::Changed("b[1].age".to_string(), Value::Int(25), Value::Int(26))];
+        upsert_diffs(&mut diffs, updates, "b[1]");
+
+        assert_eq!(diffs.len(), 4);
+        assert!(diffs.contains(&Diff::Changed("b[1].age".to_string(), Value::Int(25), Value::Int(26))));
+        assert!(!diffs.iter().any(|diff| diff.path() == "b[1].name"));
+        assert!(diffs.contains(&Diff::Changed("b[0].age".to_string(), Value::Int(30), Value::Int(31))));
+        assert!(diffs.contains(&Diff::Changed("a.x".to_string(), Value::Int(1), Value::Int(2))));
+
+        upsert_diffs(&mut diffs, vec![], "b");
+        assert_eq!(diffs.len(), 2);
+    }
+
+    #[test]
+    fn test_float_output_precision() {
+        let json1 = json!({"a": 0.1, "b": [1.23456]});
+        let json2 = json!({"a": 0.1 + 0.2, "b": [9.87654]});
+
+        let deep_diff = DeepDiff::new().float_output_precision(2);
+        let (v1, v2) = (deep_diff.json_to_value(&json1), deep_diff.json_to_value(&json2));
+
+        assert_eq!(
+            deep_diff.compare_full(&v1, &v2).to_json().to_string(),
+            r#"[{"Changed":["a",{"Float":0.1},{"Float":0.3}]},{"Changed":["b[0]",{"Float":1.23},{"Float":9.88}]}]"#
+        );
+        assert_eq!(deep_diff.compare_full(&v1, &v2).to_human(), "a: 0.1 => 0.3\nb[0]: 1.23 => 9.88");
+
+        let diffs = deep_diff.compare(&v1, &v2);
+        assert!(diffs.contains(&Diff::Changed("a".to_string(), Value::Float(0.1), Value::Float(0.1 + 0.2))));
+        assert!(deep_diff.verify_roundtrip(&v1, &v2));
+        let options = JsonOptions { float_precision: Some(1), ..JsonOptions::default() };
+        assert_eq!(diffs_to_json_with(&diffs[..1], &options)[0]["new"], json!(0.3));
+        assert_eq!(deep_diff.diffs_to_json(&diffs), deep_diff.compare_full(&v1, &v2).to_json());
+        assert_eq!(diffs_to_json_with(&diffs, &deep_diff.json_options())[1]["new"], json!(9.88));
+
+        let huge = Diff::Changed("x".to_string(), Value::Float(1e300), Value::Float(-1e300));
+        assert_eq!(huge.to_log_line_with_precision(9), huge.to_log_line());
+    }
+
+    #[test]
+    fn test_compare_bidirectional() {
+        let json1 = load_json("simple_1");
+        let json2 = load_json("simple_2");
+
+        let deep_diff = DeepDiff::new();
+        let (forward, reverse) = deep_diff.compare_bidirectional(&deep_diff.json_to_value(&json1), &deep_diff.json_to_value(&json2));
+
+        assert_eq!(forward, deep_diff.compare_json(&json1, &json2));
+        assert_eq!(reverse, invert(&forward));
+        assert!(reverse.contains(&Diff::Removed("d[3]".to_string(), Value::Int(4))));
+        assert!(reverse.contains(&Diff::Changed("c".to_string(), Value::Bool(false), Value::Bool(true))));
+    }
+
+    #[test]
+    fn test_top_level_keys_prune() {
+        let json1 = json!({"watched": {"a": 1}, "other": [{"x": 1}, {"x": 2}], "gone": 1});
+        let json2 = json!({"watched": {"a": 2}, "other": [{"x": 3}], "new": 1});
+
+        let visits = Arc::new(AtomicUsize::new(0));
+        let counter = visits.clone();
+        let deep_diff = DeepDiff::new()
+            .top_level_keys(&["watched", "missing"])
+            .array_identity(Arc::new(move |value: &Value| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                value.clone()
+            }));
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs, vec![Diff::Changed("watched.a".to_string(), Value::Int(1), Value::Int(2))]);
+        assert_eq!(visits.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_annotate_numeric_delta() {
+        let json1 = json!({"requests": 100, "errors": 0, "latency": 2.0, "name": "a"});
+        let json2 = json!({"requests": 150, "errors": 3, "latency": 1.5, "name": "b"});
+
+        let deep_diff = DeepDiff::new().annotate_numeric_delta(true);
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 4);
+        assert!(diffs.contains(&Diff::NumericChanged("requests".to_string(), Value::Int(100), Value::Int(150), Some(50.0))));
+        assert!(diffs.contains(&Diff::NumericChanged("errors".to_string(), Value::Int(0), Value::Int(3), None)));
+        assert!(diffs.contains(&Diff::NumericChanged("latency".to_string(), Value::Float(2.0), Value::Float(1.5), Some(-25.0))));
+        assert!(diffs.contains(&Diff::Changed("name".to_string(), Value::String("a".to_string()), Value::String("b".to_string()))));
+        assert_eq!(diffs.iter().find(|diff| diff.path() == "requests").unwrap().to_log_line(), "requests: 100 => 150 (+50%)");
+    }
+
+    #[test]
+    fn test_chunk_diffs_by_size() {
+        let json1 = load_json("nested_1");
+        let mut json2 = load_json("nested_2");
+        json2["big"] = json!("x".repeat(200));
+
+        let deep_diff = DeepDiff::new();
+        let diffs = deep_diff.compare_json(&json1, &json2);
+        let budget = 120;
+        let batches = chunk_diffs(diffs.clone(), budget);
+
+        assert!(batches.len() > 1);
+        assert_eq!(batches.concat(), diffs);
+        for batch in &batches {
+            let size = diffs_to_json(batch).to_string().len();
+            assert!(size <= budget || batch.len() == 1);
+        }
+        assert!(batches.iter().any(|batch| batch.len() == 1 && batch[0].path() == "big"));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let json1 = json!({"Name": "Alice", "address": {"City": "PARIS"}, "tags": ["Admin"]});
+        let json2 = json!({"name": "alice", "Address": {"city": "Paris"}, "tags": ["admin"]});
+
+        assert_eq!(DeepDiff::new().case_insensitive_strings(true).compare_json(&json1, &json2).len(), 4);
+        assert_eq!(DeepDiff::new().case_insensitive_keys(true).compare_json(&json1, &json2).len(), 3);
+
+        let deep_diff = DeepDiff::new().case_insensitive(true);
+        assert!(deep_diff.compare_json(&json1, &json2).is_empty());
+
+        let diffs = deep_diff.compare_json(&json1, &json!({"NAME": "Bob", "address": {"city": "Paris"}, "tags": ["admin"]}));
+        assert_eq!(diffs, vec![Diff::Changed("Name".to_string(), Value::String("Alice".to_string()), Value::String("Bob".to_string()))]);
+    }
+
+    #[test]
+    fn test_key_diff() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("key_diff_1"));
+        let v2 = deep_diff.json_to_value(&load_json("key_diff_2"));
+
+        let (only1, only2) = key_diff(&v1, &v2);
+
+        assert_eq!(only1, vec!["items[1].gift", "legacy", "profile.nickname"]);
+        assert_eq!(only2, vec!["items[0].discount", "profile.settings.language", "created"]);
+    }
+
+    #[test]
+    fn test_nan_equality_modes() {
+        let v1 = Value::Dict(BTreeMap::from([("x".to_string(), Value::Float(f64::NAN))]));
+        let v2 = Value::Dict(BTreeMap::from([("x".to_string(), Value::Float(f64::NAN))]));
+
+        assert!(DeepDiff::new().compare(&v1, &v2).is_empty());
+        assert!(DeepDiff::new().float_tolerance(0.1, false).compare(&v1, &v2).is_empty());
+
+        let diffs = DeepDiff::new().nan_not_equal(true).compare(&v1, &v2);
+        assert_eq!(diffs.len(), 1);
+        assert!(matches!(&diffs[0], Diff::Changed(path, Value::Float(a), Value::Float(b)) if path == "x" && a.is_nan() && b.is_nan()));
+    }
+
+    #[test]
+    fn test_numeric_strings_leading_zeros() {
+        let json1 = json!({"a": "007", "b": "1.50", "c": "10", "d": "abc", "e": "0.5"});
+        let json2 = json!({"a": "7", "b": 1.5, "c": 11, "d": "abd", "e": ".5"});
+
+        assert_eq!(DeepDiff::new().compare_json(&json1, &json2).len(), 5);
+
+        let diffs = DeepDiff::new().numeric_strings(true).compare_json(&json1, &json2);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.contains(&Diff::TypeChanged("c".to_string(), Value::String("10".to_string()), Value::Int(11))));
+        assert!(diffs.contains(&Diff::Changed("d".to_string(), Value::String("abc".to_string()), Value::String("abd".to_string()))));
+
+        let diffs = DeepDiff::new()
+            .numeric_strings(true)
+            .preserve_zero_padding(true)
+            .compare_json(&json1, &json2);
+        assert_eq!(diffs.len(), 3);
+        assert!(diffs.contains(&Diff::Changed("a".to_string(), Value::String("007".to_string()), Value::String("7".to_string()))));
+    }
+
+    #[test]
+    fn test_structured_report() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        let report = deep_diff.structured_report(&v1, &v2);
+
+        assert!(report.additions.is_empty());
+        assert!(report.removals.is_empty());
+        assert_eq!(report.modifications.len(), 5);
+        assert!(report.modifications.contains(&Modification {
+            path: vec![PathSegment::Key("a".to_string()), PathSegment::Key("y".to_string()), PathSegment::Key("z".to_string())],
+            old: Value::String("nested".to_string()),
+            new: Value::String("deeply nested".to_string()),
+        }));
+        assert!(report.modifications.contains(&Modification {
+            path: vec![PathSegment::Key("b".to_string()), PathSegment::Index(1), PathSegment::Key("name".to_string())],
+            old: Value::String("Bob".to_string()),
+            new: Value::String("Charlie".to_string()),
+        }));
+    }
+
+    #[test]
+    fn test_ignore_order_mixed_types() {
+        let json1 = json!({"items": [1, "a"]});
+        let json2 = json!({"items": ["a", 2]});
+
+        let deep_diff = DeepDiff::new().ignore_order(true);
+        let diffs = deep_diff.compare_json(&json1, &json2);
+
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.contains(&Diff::Removed("items[0]".to_string(), Value::Int(1))));
+        assert!(diffs.contains(&Diff::Added("items[1]".to_string(), Value::Int(2))));
+
+        let diffs = deep_diff.compare_json(&json!([true, 1.5, {"k": 1}]), &json!([{"k": 1}, "x", 1.5]));
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.contains(&Diff::Removed("[0]".to_string(), Value::Bool(true))));
+        assert!(diffs.contains(&Diff::Added("[1]".to_string(), Value::String("x".to_string()))));
+    }
+
+    #[test]
+    fn test_compare_dicts_stream() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        let groups: Vec<(String, Vec<Diff>)> = deep_diff.compare_dicts_stream(&v1, &v2).collect();
+        for (key, diffs) in &groups {
+            assert!(diffs.iter().all(|diff| crate::path::is_under(diff.path(), key)));
+        }
+
+        let streamed: Vec<Diff> = groups.into_iter().flat_map(|(_, diffs)| diffs).collect();
+        assert_eq!(streamed, deep_diff.compare(&v1, &v2));
+    }
+
+    #[test]
+    fn test_canonicalize_array_order() {
+        let deep_diff = DeepDiff::new().canonicalize_array_order("id");
+        let v1 = deep_diff.json_to_value(&json!([
+            {"id": 2, "name": "bob"},
+            {"id": 1, "name": "alice"},
+            {"id": 3, "name": "carol"}
+        ]));
+        let v2 = deep_diff.json_to_value(&json!([
+            {"id": 3, "name": "carol"},
+            {"id": 1, "name": "alicia"},
+            {"id": 2, "name": "bob"}
+        ]));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Changed(
+                "[0].name".to_string(),
+                Value::String("alice".to_string()),
+                Value::String("alicia".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_changed_top_level_keys() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({
+            "a": {"x": 1, "y": [1, 2]},
+            "b": {"x": 1},
+            "c": 1,
+            "d": true
+        }));
+        let v2 = deep_diff.json_to_value(&json!({
+            "a": {"x": 1, "y": [1, 3]},
+            "b": {"x": 1},
+            "c": 1,
+            "e": "new"
+        }));
+
+        let expected: BTreeSet<String> = ["a", "d", "e"].iter().map(|k| k.to_string()).collect();
+        assert_eq!(changed_top_level_keys(&v1, &v2), expected);
+        assert!(changed_top_level_keys(&v1, &v1).is_empty());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_diffs_to_yaml_round_trip() {
+        let deep_diff = DeepDiff::new().annotate_numeric_delta(true);
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+        let diffs = deep_diff.compare(&v1, &v2);
+
+        let yaml = crate::diffs_to_yaml(&diffs).unwrap();
+        let parsed: Vec<Diff> = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, diffs);
+    }
+
+    #[test]
+    fn test_dict_summary_threshold() {
+        let deep_diff = DeepDiff::new().dict_summary_threshold(1000);
+        let dict1: BTreeMap<String, Value> = (0..100_000).map(|i| (format!("key{}", i), Value::Int(i))).collect();
+        let mut dict2 = dict1.clone();
+        for i in 0..5000 {
+            dict2.insert(format!("key{}", i), Value::Int(-i - 1));
+        }
+        dict2.remove("key99999");
+        dict2.insert("extra".to_string(), Value::Bool(true));
+
+        let diffs = deep_diff.compare(&Value::Dict(dict1), &Value::Dict(dict2));
+        assert_eq!(diffs.len(), 1);
+        match &diffs[0] {
+            Diff::Summarized(path, summary) => {
+                assert_eq!(path, "");
+                assert_eq!((summary.added, summary.removed, summary.changed), (1, 1, 5000));
+                assert_eq!(summary.sample.len(), 10);
+            }
+            other => panic!("expected a summary, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_group_by_array_element() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({
+            "users": [
+                {"name": "alice", "address": {"city": "Madrid"}},
+                {"name": "bob", "age": 30},
+                {"name": "carol", "age": 40}
+            ],
+            "version": 1
+        }));
+        let v2 = deep_diff.json_to_value(&json!({
+            "users": [
+                {"name": "alice", "address": {"city": "Paris"}},
+                {"name": "bob", "age": 30},
+                {"name": "caroline", "age": 41}
+            ],
+            "version": 2
+        }));
+
+        let groups = group_by_array_element(&deep_diff.compare(&v1, &v2));
+        let keys: Vec<&str> = groups.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["", "users[0]", "users[2]"]);
+        assert_eq!(groups["users[0]"].len(), 1);
+        assert_eq!(groups["users[0]"][0].path(), "users[0].address.city");
+        let paths: Vec<&str> = groups["users[2]"].iter().map(Diff::path).collect();
+        assert_eq!(paths, vec!["users[2].age", "users[2].name"]);
+        assert_eq!(groups[""][0].path(), "version");
+    }
+
+    #[test]
+    fn test_ignore_added_empty() {
+        let deep_diff = DeepDiff::new().ignore_added_empty(true);
+        let v1 = deep_diff.json_to_value(&json!({"name": "a"}));
+        let empty = deep_diff.json_to_value(&json!({"name": "a", "meta": {}, "tags": []}));
+        let filled = deep_diff.json_to_value(&json!({"name": "a", "meta": {"x": 1}}));
+
+        assert!(deep_diff.compare(&v1, &empty).is_empty());
+        assert_eq!(
+            deep_diff.compare(&v1, &filled),
+            vec![Diff::Added(
+                "meta".to_string(),
+                deep_diff.json_to_value(&json!({"x": 1}))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_compare_at() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        assert_eq!(
+            deep_diff.compare_at(&v1, &v2, "b[1]").unwrap(),
+            vec![
+                Diff::Changed("b[1].age".to_string(), Value::Int(25), Value::Int(35)),
+                Diff::Changed(
+                    "b[1].name".to_string(),
+                    Value::String("Bob".to_string()),
+                    Value::String("Charlie".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            deep_diff.compare_at(&v1, &v2, "b[5]"),
+            Err(PathError::NotFound("b[5]".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_datetime_tolerance() {
+        let v1 = Value::String("2024-05-01T12:00:00.250Z".to_string());
+        let v2 = Value::String("2024-05-01T14:00:00.750+02:00".to_string());
+
+        assert!(DeepDiff::new().datetime_tolerance(Duration::from_secs(1)).compare(&v1, &v2).is_empty());
+        assert_eq!(
+            DeepDiff::new().datetime_tolerance(Duration::from_millis(100)).compare(&v1, &v2),
+            vec![Diff::Changed(String::new(), v1.clone(), v2.clone())]
+        );
+
+        let unparsable = Value::String("2024-05-01 noon".to_string());
+        assert_eq!(DeepDiff::new().datetime_tolerance(Duration::from_secs(1)).compare(&v1, &unparsable).len(), 1);
+    }
+
+    #[test]
+    fn test_changed_path_signature() {
+        let deep_diff = DeepDiff::new();
+        let base = deep_diff.json_to_value(&json!({"a": 1, "b": {"c": "x"}, "d": [1, 2]}));
+        let run1 = deep_diff.json_to_value(&json!({"a": 2, "b": {"c": "y"}, "d": [1, 2]}));
+        let run2 = deep_diff.json_to_value(&json!({"a": 3, "b": {"c": "z"}, "d": [1, 2]}));
+        let run3 = deep_diff.json_to_value(&json!({"a": 2, "b": {"c": "x"}, "d": [1, 3]}));
+
+        let signature1 = changed_path_signature(&deep_diff.compare(&base, &run1));
+        let signature2 = changed_path_signature(&deep_diff.compare(&base, &run2));
+        let signature3 = changed_path_signature(&deep_diff.compare(&base, &run3));
+        assert_eq!(signature1, signature2);
+        assert_ne!(signature1, signature3);
+    }
+
+    #[test]
+    fn test_ignore_line_ending_differences() {
+        let crlf = Value::String("a\r\nb\r\n".to_string());
+        let lf = Value::String("a\nb".to_string());
+
+        assert!(DeepDiff::new().ignore_line_ending_differences(true).compare(&crlf, &lf).is_empty());
+        assert_eq!(
+            DeepDiff::new().compare(&crlf, &lf),
+            vec![Diff::Changed(String::new(), crlf.clone(), lf.clone())]
+        );
+        assert_eq!(
+            DeepDiff::new().ignore_line_ending_differences(true).compare(&crlf, &Value::String("a b".to_string())).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_unordered_source_indices() {
+        let deep_diff = DeepDiff::new().ignore_order(true).unordered_source_indices(true);
+        let v1 = deep_diff.json_to_value(&json!({"items": ["d", "b", "c", "a"]}));
+        let v2 = deep_diff.json_to_value(&json!({"items": ["a", "e", "b", "d"]}));
+
+        let diffs = deep_diff.compare(&v1, &v2);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs.contains(&Diff::Removed("items[2]".to_string(), Value::String("c".to_string()))));
+        assert!(diffs.contains(&Diff::Added("items[1]".to_string(), Value::String("e".to_string()))));
+    }
+
+    #[test]
+    fn test_string_compare_field() {
+        let json1: JsonValue = serde_json::from_str(r#"{"users": [{"id": 9999999999999999999, "n": 1.5}]}"#).unwrap();
+        let json2: JsonValue = serde_json::from_str(r#"{"users": [{"id": 9999999999999999998, "n": 1.5}]}"#).unwrap();
+
+        assert_eq!(
+            DeepDiff::new().compare_json(&json1, &json2),
+            vec![Diff::Changed(
+                "users[0].id".to_string(),
+                Value::UInt(9999999999999999999),
+                Value::UInt(9999999999999999998)
+            )]
+        );
+        assert_eq!(
+            DeepDiff::new().string_compare_field("users[*].id").compare_json(&json1, &json2),
+            vec![Diff::Changed(
+                "users[0].id".to_string(),
+                Value::String("9999999999999999999".to_string()),
+                Value::String("9999999999999999998".to_string())
+            )]
+        );
+
+        let deep_diff = DeepDiff::new().string_compare_field("**.id");
+        let v1 = deep_diff.json_to_value(&json!({"a": {"id": 42}}));
+        let v2 = deep_diff.json_to_value(&json!({"a": {"id": "42"}}));
+        assert!(deep_diff.compare(&v1, &v2).is_empty());
+    }
+
+    #[test]
+    fn test_compare_full() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": [1, 2, 3], "c": "x"}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 2, "b": [1, 2], "d/e": true}));
+
+        let full = deep_diff.compare_full(&v1, &v2);
+        assert_eq!(full.diffs(), deep_diff.compare(&v1, &v2).as_slice());
+        assert_eq!(full.to_json(), diffs_to_json(full.diffs()));
+        assert_eq!(
+            full.to_human(),
+            "a: 1 => 2\nb[2]: 3 => (none)\nc: \"x\" => (none)\nd/e: (none) => true"
+        );
+        assert_eq!(
+            full.to_json_patch(),
+            json!([
+                {"op": "replace", "path": "/a", "value": 2},
+                {"op": "remove", "path": "/c"},
+                {"op": "remove", "path": "/b/2"},
+                {"op": "add", "path": "/d~1e", "value": true}
+            ])
+        );
+        assert_eq!(full.stats(), DiffStats { added: 1, removed: 2, changed: 1, total: 4 });
+    }
+
+    #[test]
+    fn test_ignore_array_values() {
+        let deep_diff = DeepDiff::new()
+            .ignore_order(true)
+            .ignore_array_values(vec![Value::String("TBD".to_string())]);
+        let v1 = deep_diff.json_to_value(&json!(["a", "TBD"]));
+        let v2 = deep_diff.json_to_value(&json!(["a"]));
+        let v3 = deep_diff.json_to_value(&json!(["TBD", "TBD", "b"]));
+
+        assert!(deep_diff.compare(&v1, &v2).is_empty());
+        assert_eq!(
+            deep_diff.compare(&v1, &v3),
+            vec![
+                Diff::Removed("[0]".to_string(), Value::String("a".to_string())),
+                Diff::Added("[2]".to_string(), Value::String("b".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let deep_diff = DeepDiff::new();
+        let defaults = deep_diff.json_to_value(&json!({
+            "server": {"host": "localhost", "port": 8080},
+            "logging": {"level": "info", "format": "text"},
+            "debug": false
+        }));
+        let actual = deep_diff.json_to_value(&json!({
+            "server": {"host": "localhost", "port": 9090, "tls": true},
+            "logging": {"level": "info"},
+            "debug": false
+        }));
+
+        let overrides = deep_diff.overrides(&defaults, &actual);
+        let paths: Vec<&str> = overrides.iter().map(Diff::path).collect();
+        assert_eq!(paths, vec!["server.port", "server.tls"]);
+        assert_eq!(
+            deep_diff.override_document(&defaults, &actual),
+            json!({"server": {"port": 9090, "tls": true}})
+        );
+    }
+
+    #[test]
+    fn test_float_sig_figs() {
+        let v1 = Value::Float(12345.0);
+        let v2 = Value::Float(12346.0);
+
+        assert!(DeepDiff::new().float_sig_figs(4).compare(&v1, &v2).is_empty());
+        assert_eq!(DeepDiff::new().float_sig_figs(5).compare(&v1, &v2).len(), 1);
+        assert!(DeepDiff::new()
+            .float_sig_figs(3)
+            .compare(&Value::Float(0.0012341), &Value::Float(0.0012339))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_root_container_type_change() {
+        let deep_diff = DeepDiff::new();
+        let dict = deep_diff.json_to_value(&json!({"a": 1}));
+        let array = deep_diff.json_to_value(&json!([1]));
+
+        assert_eq!(
+            deep_diff.compare(&Value::Dict(BTreeMap::new()), &Value::Array(Vec::new())),
+            vec![Diff::TypeChanged(String::new(), Value::Dict(BTreeMap::new()), Value::Array(Vec::new()))]
+        );
+        assert_eq!(
+            deep_diff.compare(&dict, &array),
+            vec![Diff::TypeChanged(String::new(), dict.clone(), array.clone())]
+        );
+    }
+
+    #[test]
+    fn test_value_depth_limit() {
+        let deep_diff = DeepDiff::new().value_depth_limit(1);
+        let v1 = deep_diff.json_to_value(&json!({"a": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"a": {"b": {"c": {"d": 1}}, "e": 2}}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::StructureChanged(
+                "a".to_string(),
+                Value::Int(1),
+                deep_diff.json_to_value(&json!({"b": "<truncated>", "e": 2}))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unwrap_path() {
+        let deep_diff = DeepDiff::new().unwrap_path("data");
+        let v1 = deep_diff.json_to_value(&json!({
+            "data": {"user": {"name": "alice", "age": 30}},
+            "meta": {"request_id": "abc", "took_ms": 12}
+        }));
+        let v2 = deep_diff.json_to_value(&json!({
+            "data": {"user": {"name": "alice", "age": 31}},
+            "meta": {"request_id": "def", "took_ms": 15}
+        }));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Changed("user.age".to_string(), Value::Int(30), Value::Int(31))]
+        );
+    }
+
+    #[test]
+    fn test_diffs_to_json_type_names() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": "x"}));
+        let v2 = deep_diff.json_to_value(&json!({"a": "1", "c": [true]}));
+        let diffs = deep_diff.compare(&v1, &v2);
+
+        let options = JsonOptions { type_names: true, ..Default::default() };
+        assert_eq!(
+            diffs_to_json_with(&diffs, &options),
+            json!([
+                {"kind": "type_changed", "path": "a", "old": 1, "old_type": "int", "new": "1", "new_type": "string"},
+                {"kind": "removed", "path": "b", "old": "x", "old_type": "string"},
+                {"kind": "added", "path": "c", "new": [true], "new_type": "array"}
+            ])
+        );
+        assert_eq!(
+            diffs_to_json_with(&diffs[..1], &JsonOptions::default()),
+            json!([{"kind": "type_changed", "path": "a", "old": 1, "new": "1"}])
+        );
+    }
+
+    #[test]
+    fn test_to_merge_patch_with_removals() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": {"x": 1, "y": 2}, "tags": ["a", "b", "c"]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": {"x": 5}, "tags": ["a", "b"], "new": true}));
+
+        let (patch, removals) = to_merge_patch_with_removals(&deep_diff.compare(&v1, &v2));
+        assert_eq!(patch, json!({"a": {"x": 5, "y": null}, "new": true}));
+        assert_eq!(removals, vec!["tags[2]".to_string()]);
+        assert_eq!(to_merge_patch_with_removals_with_source(&deep_diff.compare(&v1, &v2), &v2), (patch, removals));
+
+        let json1 = json!({"a": [1, 2], "b": {"c": [{"d": 1}, {"d": 2}], "e": 1}, "f": [1, 2, 3]});
+        let json2 = json!({"a": [1, 3], "b": {"c": [{"d": 1}, {"d": 5}, {"d": 6}]}, "f": [1, 2, 3]});
+        let (v1, v2) = (deep_diff.json_to_value(&json1), deep_diff.json_to_value(&json2));
+        let diffs = deep_diff.compare(&v1, &v2);
+        assert_eq!(to_merge_patch_with_removals(&diffs), (json!({"b": {"e": null}}), vec![]));
+        let (patch, removals) = to_merge_patch_with_removals_with_source(&diffs, &v2);
+        assert_eq!(patch, json!({"a": [1, 3], "b": {"c": [{"d": 1}, {"d": 5}, {"d": 6}], "e": null}}));
+        assert!(removals.is_empty());
+        assert_eq!(apply_merge_patch(json1, &patch), json2);
+    }
+
+    // RFC 7396 section 2.
+    fn apply_merge_patch(target: JsonValue, patch: &JsonValue) -> JsonValue {
+        let JsonValue::Object(fields) = patch else {
+            return patch.clone();
+        };
+        let mut target = match target {
+            JsonValue::Object(target) => target,
+            _ => serde_json::Map::new(),
+        };
+        for (key, value) in fields {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                let current = target.remove(key).unwrap_or(JsonValue::Null);
+                target.insert(key.clone(), apply_merge_patch(current, value));
+            }
+        }
+        JsonValue::Object(target)
+    }
+
+    #[test]
+    fn test_compare_cache() {
+        let scored = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&scored);
+        let deep_diff = DeepDiff::new()
+            .top_n_by(10, Arc::new(move |_: &Diff| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                0
+            }))
+            .with_cache();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        let first = deep_diff.compare(&v1, &v2);
+        let traversed = scored.load(Ordering::SeqCst);
+        assert!(traversed > 0);
+
+        assert_eq!(deep_diff.compare(&v1, &v2.clone()), first);
+        assert_eq!(scored.load(Ordering::SeqCst), traversed);
+
+        deep_diff.clear_cache();
+        assert_eq!(deep_diff.compare(&v1, &v2), first);
+        assert_eq!(scored.load(Ordering::SeqCst), traversed * 2);
+    }
+
+    #[test]
+    fn test_array_structure_only() {
+        let deep_diff = DeepDiff::new().array_structure_only(true);
+        let v1 = deep_diff.json_to_value(&json!({"values": [1, 2, "a"], "name": "x"}));
+        let v2 = deep_diff.json_to_value(&json!({"values": [10, 2, true, 4], "name": "y"}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::Changed("name".to_string(), Value::String("x".to_string()), Value::String("y".to_string())),
+                Diff::TypeChanged("values[2]".to_string(), Value::String("a".to_string()), Value::Bool(true)),
+                Diff::Added("values[3]".to_string(), Value::Int(4)),
+            ]
+        );
+
+        // Elements that differ only in value still align in every array mode.
+        let json1 = json!({"values": [1, 2, 3]});
+        let json2 = json!({"values": [1, 5, 3]});
+        let lcs = DeepDiff::new().array_structure_only(true).array_algorithm(ArrayAlgo::Lcs);
+        let unordered = DeepDiff::new().array_structure_only(true).ignore_order(true);
+        for deep_diff in [&deep_diff, &lcs, &unordered] {
+            assert!(deep_diff.compare_json(&json1, &json2).is_empty());
+        }
+        assert_eq!(
+            lcs.compare_json(&json1, &json!({"values": [1, 5, "a", 3]})),
+            vec![Diff::Added("values[2]".to_string(), Value::String("a".to_string()))]
+        );
+        // Any surplus int is reported, whichever one is left over.
+        assert_eq!(
+            unordered.compare_json(&json1, &json!({"values": [1, 5, 3, 4]})),
+            vec![Diff::Added("values[1]".to_string(), Value::Int(5))]
+        );
+    }
+
+    #[test]
+    fn test_common_prefix_depth() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        assert_eq!(common_prefix_depth(&v1, &v2), 2);
+        assert_eq!(common_prefix_depth(&v1, &v1), 3);
+        assert_eq!(common_prefix_depth(&Value::Int(1), &Value::Int(2)), 0);
+
+        let leaf1 = deep_diff.json_to_value(&json!({"a": {"b": {"c": 1}}}));
+        let leaf2 = deep_diff.json_to_value(&json!({"a": {"b": {"c": 2}}}));
+        assert_eq!(common_prefix_depth(&leaf1, &leaf2), 3);
+        assert_eq!(common_prefix_depth(&leaf1, &leaf1), 3);
+    }
+
+    #[test]
+    fn test_numeric_string_at() {
+        let deep_diff = DeepDiff::new().numeric_string_at("items[*].price");
+        let v1 = deep_diff.json_to_value(&json!({"items": [{"id": "007", "price": "9.99"}]}));
+        let v2 = deep_diff.json_to_value(&json!({"items": [{"id": 7, "price": 9.99}]}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::TypeChanged("items[0].id".to_string(), Value::String("007".to_string()), Value::Int(7))]
+        );
+    }
+
+    #[test]
+    fn test_to_canonical_bytes() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"b": [1, 2.5, "x"], "a": {"z": true, "y": 1e21, "x": 0.000001}}));
+        let v2 = deep_diff.json_to_value(&serde_json::from_str(
+            r#"{ "a" : { "x" : 1e-6, "y" : 1000000000000000000000.0, "z" : true }, "b" : [ 1, 2.50, "x" ] }"#,
+        )
+        .unwrap());
+
+        let bytes = to_canonical_bytes(&v1);
+        assert_eq!(bytes, to_canonical_bytes(&v2));
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            r#"{"a":{"x":0.000001,"y":1e+21,"z":true},"b":[1,2.5,"x"]}"#
+        );
+        assert_eq!(to_canonical_bytes(&Value::Float(1e-7)), b"1e-7");
+        assert_eq!(to_canonical_bytes(&Value::Float(-4.0)), b"-4");
+    }
+
+    #[test]
+    fn test_first_diff() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&load_json("nested_1"));
+        let v2 = deep_diff.json_to_value(&load_json("nested_2"));
+
+        assert_eq!(deep_diff.first_diff(&v1, &v2), deep_diff.compare(&v1, &v2).into_iter().next());
+        assert_eq!(
+            deep_diff.first_diff(&v1, &v2),
+            Some(Diff::Changed("a.x".to_string(), Value::Int(1), Value::Int(2)))
+        );
+        assert_eq!(deep_diff.first_diff(&v1, &v1), None);
+    }
+
+    #[test]
+    fn test_to_mutations() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"name": "a", "tags": ["x"], "old": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"name": "b", "tags": ["x", "y"]}));
+
+        assert_eq!(
+            to_mutations_with_source(&deep_diff.compare(&v1, &v2), &v1),
+            vec![
+                Mutation::Set {
+                    path: vec![PathSegment::Key("name".to_string())],
+                    value: Value::String("b".to_string()),
+                },
+                Mutation::Delete {
+                    path: vec![PathSegment::Key("old".to_string())],
+                },
+                Mutation::Append {
+                    path: vec![PathSegment::Key("tags".to_string())],
+                    value: Value::String("y".to_string()),
+                },
+            ]
+        );
+
+        // Only the source shows the element was added at the end.
+        let tags = vec![PathSegment::Key("tags".to_string()), PathSegment::Index(1)];
+        assert_eq!(
+            to_mutations(&deep_diff.compare(&v1, &v2))[2],
+            Mutation::Insert { path: tags.clone(), value: Value::String("y".to_string()) }
+        );
+        let lcs = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs);
+        let v1 = lcs.json_to_value(&json!({"tags": [1, 2]}));
+        let v2 = lcs.json_to_value(&json!({"tags": [1, 9, 2]}));
+        assert_eq!(
+            to_mutations_with_source(&lcs.compare(&v1, &v2), &v1),
+            vec![Mutation::Insert { path: tags, value: Value::Int(9) }]
+        );
+    }
+
+    #[test]
+    fn test_null_is_distinct_from_string() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": null, "b": null}));
+        let v2 = deep_diff.json_to_value(&json!({"a": "null", "b": null}));
+
+        let diffs = deep_diff.compare(&v1, &v2);
+        assert_eq!(
+            diffs,
+            vec![Diff::TypeChanged("a".to_string(), Value::Null, Value::String("null".to_string()))]
+        );
+
+        let json = diffs_to_json(&diffs);
+        assert_eq!(json, json!([{"TypeChanged": ["a", "Null", {"String": "null"}]}]));
+        let parsed: Vec<Diff> = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, diffs);
+    }
+
+    #[test]
+    fn test_tolerate_edge_edits() {
+        let deep_diff = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs).tolerate_edge_edits(1, 2);
+        let log = deep_diff.json_to_value(&json!(["a", "b", "c", "d"]));
+        let rolled = deep_diff.json_to_value(&json!(["b", "c", "d", "e", "f"]));
+        let edited = deep_diff.json_to_value(&json!(["a", "x", "c", "d"]));
+        let overflowed = deep_diff.json_to_value(&json!(["a", "b", "c", "d", "e", "f", "g"]));
+
+        assert!(deep_diff.compare(&log, &rolled).is_empty());
+        assert_eq!(
+            deep_diff.compare(&log, &edited),
+            vec![
+                Diff::Removed("[1]".to_string(), Value::String("b".to_string())),
+                Diff::Added("[1]".to_string(), Value::String("x".to_string())),
+            ]
+        );
+        assert_eq!(deep_diff.compare(&log, &overflowed).len(), 3);
+    }
+
+    #[test]
+    fn test_type_changed() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"count": 5, "shape": {"x": 1}, "flag": true, "ratio": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"count": "5", "shape": [1], "flag": null, "ratio": 1.5}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::TypeChanged("count".to_string(), Value::Int(5), Value::String("5".to_string())),
+                Diff::TypeChanged("flag".to_string(), Value::Bool(true), Value::Null),
+                Diff::Changed("ratio".to_string(), Value::Int(1), Value::Float(1.5)),
+                Diff::TypeChanged(
+                    "shape".to_string(),
+                    deep_diff.json_to_value(&json!({"x": 1})),
+                    deep_diff.json_to_value(&json!([1]))
+                ),
+            ]
+        );
+        assert_eq!(
+            diffs_to_json(&deep_diff.compare(&v1, &v2)[..1]),
+            json!([{"TypeChanged": ["count", {"Int": 5}, {"String": "5"}]}])
+        );
+    }
+
+    #[test]
+    fn test_idempotent_patch() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": {"c": "x", "d": true}, "tags": ["t1"]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 2, "b": {"c": "y"}, "tags": ["t1", "t2"], "e": null}));
+        let patch = to_idempotent_patch(&deep_diff.compare(&v1, &v2));
+
+        let applied = apply_idempotent(&v1, &patch).unwrap();
+        assert_eq!(applied, v2);
+        assert_eq!(apply_idempotent(&applied, &patch).unwrap(), v2);
+
+        let stale = deep_diff.json_to_value(&json!({"a": 3, "b": {"c": "x", "d": true}, "tags": ["t1"]}));
+        assert_eq!(apply_idempotent(&stale, &patch), Err(ApplyError::Conflict("a".to_string())));
+    }
+
+    #[test]
+    fn test_ignore_order_multiset() {
+        let deep_diff = DeepDiff::new().ignore_order(true);
+        let v1 = deep_diff.json_to_value(&json!({"a": [1, 1, 2]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [2, 1, 2]}));
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Removed("a[1]".to_string(), Value::Int(1)), Diff::Added("a[2]".to_string(), Value::Int(2))]
+        );
+
+        let v1 = deep_diff.json_to_value(&json!({"a": [3, 1, 3, 3]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [3, 1]}));
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Removed("a[2]".to_string(), Value::Int(3)), Diff::Removed("a[3]".to_string(), Value::Int(3))]
+        );
+
+        let v2 = deep_diff.json_to_value(&json!({"a": [3, 3, 1, 3, 4, 1]}));
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Added("a[5]".to_string(), Value::Int(1)), Diff::Added("a[4]".to_string(), Value::Int(4))]
+        );
+    }
+
+    #[test]
+    fn test_breaking_change_rules() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"id": 1, "name": "a", "age": 3}));
+        let v2 = deep_diff.json_to_value(&json!({"id": "1", "name": "b", "email": "x"}));
+        let breaking = |deep_diff: &DeepDiff| -> Vec<(String, bool)> {
+            deep_diff
+                .compare_breaking(&v1, &v2)
+                .into_iter()
+                .map(|annotated| (annotated.diff.path().to_string(), annotated.breaking))
+                .collect()
+        };
+
+        let mut result = breaking(&deep_diff);
+        result.sort();
+        assert_eq!(
+            result,
+            vec![
+                ("age".to_string(), true),
+                ("email".to_string(), false),
+                ("id".to_string(), true),
+                ("name".to_string(), false),
+            ]
+        );
+
+        let strict = DeepDiff::new().breaking_change_rules(BreakingRules {
+            additions: true,
+            value_changes: true,
+            ..BreakingRules::default()
+        });
+        assert!(breaking(&strict).iter().all(|(_, breaking)| *breaking));
+    }
+
+    #[test]
+    fn test_ignore_order_element_paths() {
+        let v1 = DeepDiff::new().json_to_value(&json!({"items": ["x", "y", "z"]}));
+        let v2 = DeepDiff::new().json_to_value(&json!({"items": ["z", "w"]}));
+
+        assert_eq!(
+            DeepDiff::new().ignore_order(true).compare(&v1, &v2),
+            vec![
+                Diff::Added("items[1]".to_string(), Value::String("w".to_string())),
+                Diff::Removed("items[0]".to_string(), Value::String("x".to_string())),
+                Diff::Removed("items[1]".to_string(), Value::String("y".to_string())),
+            ]
+        );
+
+        let collapsed = DeepDiff::new().ignore_order(true).unordered_source_indices(false).compare(&v1, &v2);
+        assert!(collapsed.iter().all(|diff| diff.path() == "items"));
+    }
+
+    #[test]
+    fn test_discriminator_field() {
+        let deep_diff = DeepDiff::new().discriminator_field("type");
+        let v1 = deep_diff.json_to_value(&json!([{"type": "A", "x": 1}, {"type": "B", "x": 1}, {"type": "A", "x": 3}]));
+        let v2 = deep_diff.json_to_value(&json!([{"type": "B", "x": 2}, {"type": "A", "x": 1}, {"type": "C", "x": 3}]));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::Changed("[1].x".to_string(), Value::Int(1), Value::Int(2)),
+                Diff::Removed("[2]".to_string(), v1.get_path("[2]").unwrap().clone()),
+                Diff::Added("[2]".to_string(), v2.get_path("[2]").unwrap().clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_key_paths() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a.b": 1, "a": {"b": 1}, "root": {"x[0]": [1], "q\"]": 1}}));
+        let v2 = deep_diff.json_to_value(&json!({"a.b": 2, "a": {"b": 2}, "root": {"x[0]": [2], "q\"]": 2}}));
+
+        let diffs = deep_diff.compare(&v1, &v2);
+        let paths: Vec<&str> = diffs.iter().map(Diff::path).collect();
+        assert_eq!(paths, vec!["a.b", "[\"a.b\"]", "root[\"q\\\"]\"]", "root[\"x[0]\"][0]"]);
+
+        for diff in &diffs {
+            assert_eq!(v1.get_path(diff.path()).unwrap(), &Value::Int(1));
+        }
+        assert_eq!(
+            crate::path::parse_path("root[\"x[0]\"][0]"),
+            vec![
+                PathSegment::Key("root".to_string()),
+                PathSegment::Key("x[0]".to_string()),
+                PathSegment::Index(0)
+            ]
+        );
+        assert_eq!(crate::to_json_patch(&diffs)[1]["path"], json!("/a.b"));
+
+        let v1 = deep_diff.json_to_value(&json!({"": 1, "a": {"": [1]}}));
+        let v2 = deep_diff.json_to_value(&json!({"": 2, "a": {"": [1, 2]}}));
+        let diffs = deep_diff.compare(&v1, &v2);
+        let paths: Vec<&str> = diffs.iter().map(Diff::path).collect();
+        assert_eq!(paths, vec!["[\"\"]", "a[\"\"][1]"]);
+        assert_eq!(apply(&v1, &diffs).unwrap(), v2);
+    }
+
+    #[test]
+    fn test_json_pointer_paths() {
+        let deep_diff = DeepDiff::new().path_format(PathFormat::JsonPointer);
+        let json1 = json!({"a": {"b": [1, {"c": 2}]}, "x/y": {"~": 1}, "d": true});
+        let json2 = json!({"a": {"b": [1, {"c": 3}, 4]}, "x/y": {"~": 2}});
+
+        assert_eq!(
+            deep_diff.compare_json(&json1, &json2),
+            vec![
+                Diff::Changed("/a/b/1/c".to_string(), Value::Int(2), Value::Int(3)),
+                Diff::Added("/a/b/2".to_string(), Value::Int(4)),
+                Diff::Removed("/d".to_string(), Value::Bool(true)),
+                Diff::Changed("/x~1y/~0".to_string(), Value::Int(1), Value::Int(2)),
+            ]
+        );
+        assert_eq!(deep_diff.compare_json(&json!(1), &json!(2))[0].path(), "");
+    }
+
+    #[test]
+    fn test_compare_json_str_located() {
+        let s1 = "{\n  \"name\": \"a\",\n  \"tags\": [\"x\", \"y\"],\n  \"k\\u00e9y\": 1\n}";
+        let s2 = "{\"name\": \"b\", \"tags\": [\"x\"],\n \"k\u{e9}y\": {\"deep\": true}}";
+
+        let located = DeepDiff::new().compare_json_str_located(s1, s2).unwrap();
+        let positions: Vec<_> = located.iter().map(|l| (l.diff.path(), l.old, l.new)).collect();
+        let at = |line, column| Some(Position { line, column });
+        assert_eq!(
+            positions,
+            vec![
+                ("k\u{e9}y", at(4, 15), at(2, 9)),
+                ("name", at(2, 11), at(1, 10)),
+                ("tags[1]", at(3, 17), None),
+            ]
+        );
+
+        assert!(DeepDiff::new().compare_json_str_located("{", "{}").is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let deep_diff = DeepDiff::new().defaults(BTreeMap::from([
+            ("settings.theme".to_string(), Value::String("light".to_string())),
+            ("retries".to_string(), Value::Int(3)),
+        ]));
+        let v1 = deep_diff.json_to_value(&json!({"settings": {"theme": "light"}, "name": "a"}));
+        let v2 = deep_diff.json_to_value(&json!({"settings": {}, "name": "a", "retries": 3}));
+        assert!(deep_diff.compare(&v1, &v2).is_empty());
+
+        let v3 = deep_diff.json_to_value(&json!({"settings": {}, "name": "a", "retries": 5}));
+        assert_eq!(deep_diff.compare(&v1, &v3), vec![Diff::Added("retries".to_string(), Value::Int(5))]);
+    }
+
+    #[test]
+    fn test_apply_round_trip() {
+        let pairs = [
+            (json!({"a": 1, "b": {"c": [1, 2, 3]}, "d": "x"}), json!({"a": 2, "b": {"c": [1, 4]}, "e": [true]})),
+            (json!([1, 2, 3, 4]), json!([0, 1, 3, 4, 5])),
+            (json!({"a.b": {"k": null}, "x": [{"y": 1}]}), json!({"a.b": {"k": 1.5}, "x": [{"y": 1}, {"z": 2}]})),
+            (json!({"a": [1, 2]}), json!("scalar")),
+        ];
+        for deep_diff in [DeepDiff::new(), DeepDiff::new().array_algorithm(ArrayAlgo::Lcs)] {
+            for (json1, json2) in &pairs {
+                let (v1, v2) = (deep_diff.json_to_value(json1), deep_diff.json_to_value(json2));
+                assert_eq!(apply(&v1, &deep_diff.compare(&v1, &v2)).unwrap(), v2);
+            }
+        }
+
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": 2}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 5}));
+        let diffs = deep_diff.compare(&v1, &v2);
+        let stale = deep_diff.json_to_value(&json!({"a": 3, "b": 2}));
+        assert_eq!(apply(&stale, &diffs), Err(ApplyError::Conflict("a".to_string())));
+        let missing = deep_diff.json_to_value(&json!({"a": 1}));
+        assert_eq!(apply(&missing, &diffs), Err(ApplyError::PathNotFound("b".to_string())));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_compare_files() {
+        use std::path::Path;
+
+        let deep_diff = DeepDiff::new();
+        for name in ["simple", "nested", "array_order", "float_comparison", "key_diff"] {
+            let path1 = format!("test_cases/{}_1.json", name);
+            let path2 = format!("test_cases/{}_2.json", name);
+            assert_eq!(
+                deep_diff.compare_files(Path::new(&path1), Path::new(&path2)).unwrap(),
+                deep_diff.compare_json(&load_json(&format!("{}_1", name)), &load_json(&format!("{}_2", name)))
+            );
+        }
+
+        let broken = std::env::temp_dir().join("fast_deep_diff_broken.json");
+        fs::write(&broken, "{\"a\": [1, 2,\n}").unwrap();
+        let err = deep_diff.compare_files(Path::new("test_cases/simple_1.json"), &broken).unwrap_err();
+        assert!(matches!(err, crate::FileError::Parse(ref path, _) if *path == broken));
+        assert!(err.to_string().contains("line 2"));
+        fs::remove_file(&broken).unwrap();
+
+        assert!(matches!(
+            deep_diff.compare_files(Path::new("test_cases/missing.json"), Path::new("test_cases/simple_2.json")),
+            Err(crate::FileError::Io(..))
+        ));
+    }
+
+    #[test]
+    fn test_apply_inverted() {
+        let json1 = json!({"a": {"b": [1, {"c": "x"}, 3]}, "d": [true], "e": {"f": null}});
+        let json2 = json!({"a": {"b": [2, {"c": "y", "g": [1]}]}, "d": [true, false, {"h": 1}], "i": 1.5});
+
+        for deep_diff in [DeepDiff::new(), DeepDiff::new().array_algorithm(ArrayAlgo::Lcs)] {
+            let (v1, v2) = (deep_diff.json_to_value(&json1), deep_diff.json_to_value(&json2));
+            let diffs = deep_diff.compare(&v1, &v2);
+            assert_eq!(apply(&v2, &invert(&diffs)).unwrap(), v1);
+        }
+
+        let deep_diff = DeepDiff::new().ignore_order(true);
+        let v1 = deep_diff.json_to_value(&json!({"a": [5, 1, 4, 1], "b": [[1, 2], [3]]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [1, 9, 5], "b": [[3], [2, 1], [4]]}));
+        let restored = apply(&v2, &invert(&deep_diff.compare(&v1, &v2))).unwrap();
+        assert!(deep_diff.compare(&restored, &v1).is_empty());
+    }
+
+    #[test]
+    fn test_report_reorders() {
+        let deep_diff = DeepDiff::new().ignore_order(true).report_reorders(true);
+        let v1 = deep_diff.json_to_value(&json!({"a": [1, 2, 2, 3], "b": [1, 1], "c": [1, 2]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [2, 3, 1, 2], "b": [1, 1], "c": [2, 3]}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::Reordered("a".to_string()),
+                Diff::Removed("c[0]".to_string(), Value::Int(1)),
+                Diff::Added("c[1]".to_string(), Value::Int(3)),
+            ]
+        );
+        assert_eq!(deep_diff.compare(&v1, &v2)[0].to_log_line(), "a: reordered");
+        assert!(DeepDiff::new().ignore_order(true).compare(&v1, &v2).iter().all(|diff| diff.path().starts_with('c')));
+    }
+
+    #[test]
+    fn test_value_from_json() {
+        let json = json!({"a": [1, -2, 1.5, "x", true, null], "b": {"c": {}}, "big": 18446744073709551615u64});
+        let value = Value::from(&json);
+
+        assert_eq!(value.get_path("a[5]"), Ok(&Value::Null));
+        assert_eq!(value.get_path("a[1]"), Ok(&Value::Int(-2)));
+        assert_eq!(value.get_path("big"), Ok(&Value::UInt(u64::MAX)));
+        assert_eq!(Value::from(json.clone()), value);
+        assert_eq!(DeepDiff::new().json_to_value(&json), value);
+
+        let round_trip = JsonValue::from(Value::from(&json));
+        assert_eq!(round_trip["a"], json["a"]);
+        assert_eq!(round_trip["b"], json["b"]);
+        assert_eq!(JsonValue::from(&value), round_trip);
+    }
+
+    #[test]
+    fn test_classify_changes() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": 2.5, "c": "x", "d": true, "e": 1, "f": [1], "g": 3}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 2, "b": 1, "c": "y", "d": false, "e": "1", "f": [1, 2], "h": 4}));
+
+        assert_eq!(
+            classify_changes(&deep_diff.compare(&v1, &v2)),
+            vec![
+                ("a".to_string(), ChangeClass::IncreaseNumeric),
+                ("b".to_string(), ChangeClass::DecreaseNumeric),
+                ("c".to_string(), ChangeClass::StringEdit),
+                ("d".to_string(), ChangeClass::BoolFlip),
+                ("e".to_string(), ChangeClass::TypeChange),
+                ("f[1]".to_string(), ChangeClass::Addition),
+                ("g".to_string(), ChangeClass::Removal),
+                ("h".to_string(), ChangeClass::Addition),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_value_serde_round_trip() {
+        let value = Value::from(&json!({
+            "ints": [2, -7, 9007199254740993i64],
+            "floats": [2.0, -0.5, 1e300],
+            "nested": {"a": [null, true, "s", {"b": []}]},
+        }));
+
+        let encoded = serde_json::to_string(&value).unwrap();
+        let decoded: Value = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, value);
+        assert_eq!(decoded.get_path("ints[0]"), Ok(&Value::Int(2)));
+        assert_eq!(decoded.get_path("floats[0]"), Ok(&Value::Float(2.0)));
+        assert_eq!(decoded.get_path("ints[2]"), Ok(&Value::Int(9007199254740993)));
+
+        let special = Value::Array(vec![Value::Float(f64::NAN), Value::Float(f64::INFINITY), Value::Float(f64::NEG_INFINITY)]);
+        let encoded = serde_json::to_string(&special).unwrap();
+        assert_eq!(encoded, r#"{"Array":[{"Float":"NaN"},{"Float":"inf"},{"Float":"-inf"}]}"#);
+        let decoded: Value = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, special);
+        assert!(serde_json::from_str::<Value>(r#"{"Float":"nan"}"#).is_err());
+    }
+
+    #[test]
+    fn test_unset_sentinels() {
+        let deep_diff = DeepDiff::new().unset_sentinels(vec![Value::Int(-1), Value::String(String::new())]);
+        let v1 = deep_diff.json_to_value(&json!({"limit": -1, "name": "", "tag": "", "n": 0}));
+        let v2 = deep_diff.json_to_value(&json!({"tag": -1, "n": -1}));
+
+        assert_eq!(deep_diff.compare(&v1, &v2), vec![Diff::Changed("n".to_string(), Value::Int(0), Value::Int(-1))]);
+        assert_eq!(DeepDiff::new().compare(&v1, &v2).len(), 4);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_compare_yaml() {
+        let y1: serde_yaml::Value = serde_yaml::from_str(
+            "name: svc\nreplicas: 3\nratio: 0.5\nports: [80, 443]\n1: one\ntrue: yes\n? [a, b]\n: pair\nimage: !tagged app:1\n",
+        )
+        .unwrap();
+        let y2: serde_yaml::Value = serde_yaml::from_str(
+            "name: svc\nreplicas: 4\nratio: 0.50001\nports: [443, 80]\n1: uno\ntrue: yes\n? [a, b]\n: pair\nimage: !tagged app:2\n",
+        )
+        .unwrap();
+
+        let deep_diff = DeepDiff::new().ignore_order(true).float_tolerance(0.001, false);
+        assert_eq!(
+            deep_diff.compare_yaml(&y1, &y2),
+            vec![
+                Diff::Changed("1".to_string(), Value::String("one".to_string()), Value::String("uno".to_string())),
+                Diff::Changed("image".to_string(), Value::String("app:1".to_string()), Value::String("app:2".to_string())),
+                Diff::Changed("replicas".to_string(), Value::Int(3), Value::Int(4)),
+            ]
+        );
+        let v1 = deep_diff.yaml_to_value(&y1);
+        assert_eq!(v1.get_path("[\"[\\\"a\\\",\\\"b\\\"]\"]"), Ok(&Value::String("pair".to_string())));
+        assert_eq!(v1.get_path("true"), Ok(&Value::String("yes".to_string())));
+    }
+
+    #[test]
+    fn test_patch_id() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": [1, 2], "c": "x"}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 2, "b": [1], "d": {"e": null}}));
+        let diffs = deep_diff.compare(&v1, &v2);
+        let id = patch_id(&diffs);
+
+        assert_eq!(id.len(), 16);
+        assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+        let mut reversed = diffs.clone();
+        reversed.reverse();
+        assert_eq!(patch_id(&reversed), id);
+        assert_ne!(patch_id(&invert(&diffs)), id);
+        assert_ne!(patch_id(&diffs[1..]), id);
+
+        let ints = vec![Diff::Changed("a".to_string(), Value::Int(1), Value::Int(2))];
+        let floats = vec![Diff::Changed("a".to_string(), Value::Float(1.0), Value::Float(2.0))];
+        assert_eq!(patch_id(&ints), patch_id(&floats));
+        // FNV-1a over the length as 8 little-endian bytes, then `changed\0a\01\02`.
+        assert_eq!(patch_id(&ints), "49b2aa48058a14aa");
+
+        let summarizing = DeepDiff::new().dict_summary_threshold(1);
+        let base = json!({"s": {"x": 1, "y": 1}});
+        let first = summarizing.compare_json(&base, &json!({"s": {"x": 2, "y": 1}})).remove(0);
+        let second = summarizing.compare_json(&base, &json!({"s": {"x": 1, "y": 2}})).remove(0);
+        assert_eq!(first.to_log_line(), "s: 0 added, 0 removed, 1 changed (sample: s.x: 1 => 2)");
+        assert_ne!(first.to_log_line(), second.to_log_line());
+        assert_ne!(patch_id(&[first]), patch_id(&[second]));
+    }
+
+    #[test]
+    fn test_uint_values() {
+        let json1: JsonValue = serde_json::from_str(r#"{"a": 18446744073709551615, "b": 9223372036854775808, "c": 5}"#).unwrap();
+        let json2: JsonValue = serde_json::from_str(r#"{"a": 18446744073709551614, "b": 9223372036854775808, "c": 5}"#).unwrap();
+
+        assert_eq!(
+            DeepDiff::new().compare_json(&json1, &json2),
+            vec![Diff::Changed("a".to_string(), Value::UInt(u64::MAX), Value::UInt(u64::MAX - 1))]
+        );
+        assert_eq!(JsonValue::from(Value::from(&json1)), json1);
+
+        assert_eq!(Value::UInt(5), Value::Int(5));
+        assert_eq!(Value::UInt(5).content_hash(), Value::Int(5).content_hash());
+        assert_ne!(Value::UInt(u64::MAX), Value::Int(-1));
+        assert!(Value::Int(i64::MAX) < Value::UInt(i64::MAX as u64 + 1));
+        assert!(Value::Int(-1) < Value::UInt(0));
+        assert!(DeepDiff::new().compare(&Value::Int(7), &Value::UInt(7)).is_empty());
+        assert_eq!(
+            DeepDiff::new().compare(&Value::Int(7), &Value::UInt(u64::MAX)),
+            vec![Diff::Changed(String::new(), Value::Int(7), Value::UInt(u64::MAX))]
+        );
+    }
+
+    #[test]
+    fn test_unwrap_singleton_arrays() {
+        let deep_diff = DeepDiff::new().unwrap_singleton_arrays(true);
+        let v1 = deep_diff.json_to_value(&json!({"a": ["x"], "b": 1, "c": [{"d": 1}], "e": ["x", "y"], "f": ["x"]}));
+        let v2 = deep_diff.json_to_value(&json!({"a": "x", "b": [1], "c": {"d": 2}, "e": "x", "f": "y"}));
+
+        let diffs = deep_diff.compare(&v1, &v2);
+        assert_eq!(diffs.iter().map(Diff::path).collect::<Vec<_>>(), vec!["c.d", "e", "f"]);
+        assert_eq!(diffs[2], Diff::Changed("f".to_string(), Value::String("x".to_string()), Value::String("y".to_string())));
+        assert_eq!(DeepDiff::new().compare(&v1, &v2).len(), 5);
+    }
+
+    #[test]
+    fn test_numeric_coercion() {
+        let int = Value::Int(1);
+        let exact = Value::Float(1.0);
+        let close = Value::Float(1.05);
+        let far = Value::Float(1.5);
+
+        assert_eq!(DeepDiff::new().compare(&int, &exact).len(), 1);
+
+        let deep_diff = DeepDiff::new().numeric_coercion(true);
+        assert!(deep_diff.compare(&int, &exact).is_empty());
+        assert!(deep_diff.compare(&exact, &int).is_empty());
+        assert_eq!(deep_diff.compare(&int, &far), vec![Diff::Changed(String::new(), int.clone(), far.clone())]);
+        assert_eq!(deep_diff.compare(&int, &close).len(), 1);
+
+        let tolerant = DeepDiff::new().numeric_coercion(true).float_tolerance(0.1, false);
+        assert!(tolerant.compare(&int, &close).is_empty());
+        assert!(tolerant.compare(&Value::UInt(u64::MAX), &Value::Float(u64::MAX as f64)).is_empty());
+        assert_eq!(tolerant.compare(&int, &far).len(), 1);
+
+        let percent = DeepDiff::new().numeric_coercion(true).float_tolerance(0.1, true);
+        assert!(percent.compare_json(&json!({"a": 0}), &json!({"a": 0.0})).is_empty());
+        assert!(percent.compare(&Value::Int(0), &Value::Float(-0.0)).is_empty());
+        assert!(percent.compare(&Value::Int(100), &Value::Float(105.0)).is_empty());
+        assert_eq!(percent.compare(&Value::Int(100), &Value::Float(125.0)).len(), 1);
+        assert_eq!(percent.compare(&Value::Int(0), &Value::Float(0.5)).len(), 1);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let json1 = json!({"a": {"b": {"c": 1, "d": 2}, "e": [1, 2]}, "f": 1});
+        let json2 = json!({"a": {"b": {"c": 3, "d": 4}, "e": [1, 3]}, "f": 2});
+
+        let counts: Vec<usize> = [3, 2, 1, 0]
+            .iter()
+            .map(|&depth| DeepDiff::new().max_depth(depth).compare_json(&json1, &json2).len())
+            .collect();
+        assert_eq!(counts, vec![4, 3, 2, 1]);
+        assert_eq!(DeepDiff::new().compare_json(&json1, &json2).len(), 4);
+
+        let deep_diff = DeepDiff::new().max_depth(1);
+        let (v1, v2) = (deep_diff.json_to_value(&json1), deep_diff.json_to_value(&json2));
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::Changed("a".to_string(), v1.get_path("a").unwrap().clone(), v2.get_path("a").unwrap().clone()),
+                Diff::Changed("f".to_string(), Value::Int(1), Value::Int(2)),
+            ]
+        );
+        let tolerant = DeepDiff::new().max_depth(0).float_tolerance(0.5, false);
+        assert!(tolerant.compare_json(&json!({"a": [1.0]}), &json!({"a": [1.2]})).is_empty());
+
+        for path in ["", "a", "a.b[0]", "[\"a.b\"][2].c", "a[0]b", "a[id=\"x]\"].b", ".a", "a[x]"] {
+            assert_eq!(crate::path::path_depth(path), crate::path::parse_path(path).len(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_diffs_to_json_segments() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"b": [{"age": 1}, {"age": 2}], "a.b": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"b": [{"age": 1}, {"age": 3}], "a.b": 2}));
+        let diffs = deep_diff.compare(&v1, &v2);
+
+        let options = JsonOptions { segments: true, ..Default::default() };
+        let json = diffs_to_json_with(&diffs, &options);
+        let segments: Vec<_> = json.as_array().unwrap().iter().map(|diff| diff["segments"].clone()).collect();
+        assert_eq!(segments, vec![json!(["a.b"]), json!(["b", 1, "age"])]);
+        assert_eq!(json[1]["path"], json!("b[1].age"));
+    }
+
+    #[test]
+    fn test_compare_filtered() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": 2, "c": {"d": 3, "e": 4}}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 5, "c": {"e": 4, "f": 6}}));
+
+        let seen = std::cell::Cell::new(0);
+        let removals = deep_diff.compare_filtered(&v1, &v2, |diff| {
+            seen.set(seen.get() + 1);
+            matches!(diff, Diff::Removed(..))
+        });
+        assert_eq!(
+            removals,
+            vec![
+                Diff::Removed("b".to_string(), Value::Int(2)),
+                Diff::Removed("c.d".to_string(), Value::Int(3)),
+            ]
+        );
+        assert_eq!(seen.get(), deep_diff.compare(&v1, &v2).len());
+    }
+
+    #[test]
+    fn test_ignore_keys() {
+        let deep_diff = DeepDiff::new().ignore_keys(vec!["updated_at".to_string(), "request_id".to_string()]);
+        let v1 = deep_diff.json_to_value(&json!({
+            "updated_at": 1,
+            "request_id": "a",
+            "items": [{"id": 1, "updated_at": 1}]
+        }));
+        let v2 = deep_diff.json_to_value(&json!({
+            "updated_at": 2,
+            "items": [{"id": 2, "updated_at": 2, "request_id": "b"}]
+        }));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Changed("items[0].id".to_string(), Value::Int(1), Value::Int(2))]
+        );
+    }
+
+    #[cfg(feature = "graphemes")]
+    #[test]
+    fn test_compare_text_grapheme_edit() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"name": "hi 👨‍👩‍👧 e\u{301}!", "n": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"name": "hi 👨‍👩‍👦 e\u{301}!", "n": 2}));
+
+        let diffs = deep_diff.compare_text(&v1, &v2);
+        assert_eq!(diffs[0].edit, None);
+        assert_eq!(
+            diffs[1].edit,
+            Some(crate::GraphemeEdit {
+                start: 3,
+                removed: "👨‍👩‍👧".to_string(),
+                inserted: "👨‍👩‍👦".to_string(),
+            })
+        );
+
+        let edit = crate::GraphemeEdit::between("cafe\u{301}", "cafe");
+        assert_eq!((edit.start, edit.removed.as_str(), edit.inserted.as_str()), (3, "e\u{301}", "e"));
+    }
+
+    #[test]
+    fn test_only_and_exclude_paths() {
+        let v1 = json!({
+            "users": [{"email": "a@x", "name": "A"}, {"email": "b@x", "name": "B"}],
+            "meta": {"rev": 1, "tags": [{"id": 1}]},
+            "title": "old"
+        });
+        let v2 = json!({
+            "users": [{"email": "a@y", "name": "AA"}, {"email": "b@y", "name": "BB"}],
+            "meta": {"rev": 2, "tags": [{"id": 2}]},
+            "title": "new"
+        });
+        let paths = |deep_diff: DeepDiff| -> Vec<String> {
+            deep_diff
+                .compare_json(&v1, &v2)
+                .iter()
+                .map(|diff| diff.path().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            paths(DeepDiff::new().only_paths(vec!["users.*.email".to_string()])),
+            vec!["users[0].email", "users[1].email"]
+        );
+        assert_eq!(
+            paths(DeepDiff::new().only_paths(vec!["meta.**".to_string()])),
+            vec!["meta.rev", "meta.tags[0].id"]
+        );
+        assert_eq!(
+            paths(DeepDiff::new().exclude_paths(vec!["**.id".to_string(), "users[*].name".to_string()])),
+            vec!["meta.rev", "title", "users[0].email", "users[1].email"]
+        );
+        assert_eq!(
+            paths(
+                DeepDiff::new()
+                    .only_paths(vec!["users.**".to_string(), "title".to_string()])
+                    .exclude_paths(vec!["users[1]".to_string(), "**.name".to_string()])
+            ),
+            vec!["title", "users[0].email"]
+        );
+    }
+
+    #[test]
+    fn test_verify_roundtrip() {
+        let deep_diff = DeepDiff::new();
+        for name in ["simple", "nested", "array_order", "float_comparison", "key_diff"] {
+            let v1 = deep_diff.json_to_value(&load_json(&format!("{}_1", name)));
+            let v2 = deep_diff.json_to_value(&load_json(&format!("{}_2", name)));
+            assert!(deep_diff.verify_roundtrip(&v1, &v2), "{}", name);
+        }
+
+        // A tolerance hides the change, so applying the diffs can't rebuild v2.
+        let tolerant = DeepDiff::new().float_tolerance(0.1, false);
+        let v1 = tolerant.json_to_value(&json!({"a": 1.0, "b": "x"}));
+        let v2 = tolerant.json_to_value(&json!({"a": 1.05, "b": "y"}));
+        assert!(!tolerant.verify_roundtrip(&v1, &v2));
+        assert!(deep_diff.verify_roundtrip(&v1, &v2));
+    }
+
+    #[test]
+    fn test_separator() {
+        let deep_diff = DeepDiff::new().separator("/".to_string());
+        let v1 = deep_diff.json_to_value(&json!({"a": {"b": {"c": 1}}, "d": [{"e": 1}], "f/g": 1, "h.i": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"a": {"b": {"c": 2}}, "d": [{"e": 2}], "f/g": 2, "h.i": 2}));
+
+        let paths: Vec<_> = deep_diff.compare(&v1, &v2).iter().map(|diff| diff.path().to_string()).collect();
+        assert_eq!(paths, vec!["a/b/c", "d[0]/e", "[\"f/g\"]", "h.i"]);
+
+        let arrow = DeepDiff::new().separator("->".to_string());
+        assert_eq!(arrow.compare(&v1, &v2)[0].path(), "a->b->c");
+    }
+
+    #[test]
+    #[should_panic(expected = "path separator must not be empty")]
+    fn test_empty_separator() {
+        let _ = DeepDiff::new().separator(String::new());
+    }
+
+    #[test]
+    fn test_lcs_duplicate_elements() {
+        let deep_diff = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs);
+        let v1 = deep_diff.json_to_value(&json!(["a", "a", "b"]));
+        let v2 = deep_diff.json_to_value(&json!(["a", "b"]));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Removed("[1]".to_string(), Value::String("a".to_string()))]
+        );
+        assert_eq!(
+            DeepDiff::new().compare(&v1, &v2).len(),
+            2,
+            "positional comparison reports a change and a removal"
+        );
+
+        let v3 = deep_diff.json_to_value(&json!([{"id": 1}, {"id": 1}, {"id": 2}, {"id": 1}]));
+        let v4 = deep_diff.json_to_value(&json!([{"id": 1}, {"id": 2}, {"id": 1}, {"id": 1}]));
+        let diffs = deep_diff.compare(&v3, &v4);
+        assert_eq!(
+            diffs,
+            vec![
+                Diff::Removed("[1]".to_string(), deep_diff.json_to_value(&json!({"id": 1}))),
+                Diff::Added("[3]".to_string(), deep_diff.json_to_value(&json!({"id": 1}))),
+            ]
+        );
+        assert_eq!(apply(&v3, &diffs).unwrap(), v4);
+    }
+
+    #[test]
+    fn test_summary() {
+        let deep_diff = DeepDiff::new().dict_summary_threshold(2);
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": [1, 2], "c": {"x": 1, "y": 2, "z": 3}}));
+        let v2 = deep_diff.json_to_value(&json!({"a": "1", "b": [1], "c": {"x": 2, "w": 0}, "d": true}));
+        let diffs = deep_diff.compare(&v1, &v2);
+
+        let stats = crate::summary(&diffs);
+        assert_eq!(stats, DiffStats { added: 2, removed: 3, changed: 2, total: 7 });
+        assert_eq!(
+            serde_json::to_value(stats).unwrap(),
+            json!({"added": 2, "removed": 3, "changed": 2, "total": 7})
+        );
+        assert_eq!(crate::summary(&[]).total, 0);
+
+        let json1 = json!({"c": {"x": 1, "y": 2, "z": 3}, "d": {"x": 1, "y": 2, "z": 3}});
+        let json2 = json!({"c": {"x": 2, "y": 3, "z": 4}, "d": {"x": 2, "y": 3, "z": 4}});
+        let filtered = DeepDiff::new().dict_summary_threshold(2).only_paths(vec!["d".to_string()]);
+        let diffs = filtered.compare_json(&json1, &json2);
+        assert_eq!(diffs.iter().map(Diff::path).collect::<Vec<_>>(), vec!["d"]);
+        let narrowed = DeepDiff::new().dict_summary_threshold(2).only_paths(vec!["c.x".to_string()]);
+        assert!(narrowed.compare_json(&json1, &json2).is_empty());
+        let pointer = DeepDiff::new().dict_summary_threshold(2).path_format(PathFormat::JsonPointer);
+        assert_eq!(pointer.compare_json(&json1, &json2)[0].path(), "/c");
+    }
+
+    #[test]
+    fn test_on_metrics() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let deep_diff = DeepDiff::new().on_metrics(Arc::new(move |metrics: &crate::DiffMetrics| {
+            recorder.lock().unwrap().push(*metrics);
+        }));
+
+        // Every node differs, so each of the 7 is visited.
+        let v1 = deep_diff.json_to_value(&json!({"a": 1, "b": [1, 2], "c": {"d": "x"}}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 2, "b": [3, 4], "c": {"d": "y"}}));
+        let diffs = deep_diff.compare(&v1, &v2);
+
+        // Identical subtrees are visited once, without descending.
+        let v3 = deep_diff.json_to_value(&json!({"a": 2, "b": [1, 2], "c": {"d": "x"}}));
+        deep_diff.compare(&v1, &v3);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!((seen[0].nodes, seen[0].diffs), (7, diffs.len()));
+        assert_eq!((seen[1].nodes, seen[1].diffs), (4, 1));
+    }
+
+    #[test]
+    fn test_array_key() {
+        let deep_diff = DeepDiff::new().array_key("id");
+        let v1 = deep_diff.json_to_value(&json!({"users": [
+            {"id": 1, "name": "a"},
+            {"id": 2, "name": "b"},
+            {"id": 3, "name": "c"}
+        ]}));
+        let v2 = deep_diff.json_to_value(&json!({"users": [
+            {"id": 2, "name": "B"},
+            {"id": 4, "name": "d"},
+            {"id": 1, "name": "a"}
+        ]}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![
+                Diff::Changed(
+                    "users[id=2].name".to_string(),
+                    Value::String("b".to_string()),
+                    Value::String("B".to_string())
+                ),
+                Diff::Removed("users[id=3]".to_string(), v1.get_path("users[2]").unwrap().clone()),
+                Diff::Added("users[id=4]".to_string(), v2.get_path("users[1]").unwrap().clone()),
+            ]
+        );
+
+        let v3 = deep_diff.json_to_value(&json!([{"id": "x", "n": 1}, {"n": 2}]));
+        let v4 = deep_diff.json_to_value(&json!([{"id": "x", "n": 3}, {"n": 2}]));
+        let paths: Vec<_> = deep_diff.compare(&v3, &v4).iter().map(|diff| diff.path().to_string()).collect();
+        assert_eq!(paths, vec!["[id=\"x\"].n"]);
+    }
+
+    #[test]
+    fn test_array_key_paths() {
+        assert_eq!(
+            crate::path::parse_path("users[id=2].name"),
+            vec![
+                PathSegment::Key("users".to_string()),
+                PathSegment::Keyed("id".to_string(), "2".to_string()),
+                PathSegment::Key("name".to_string()),
+            ]
+        );
+        assert_eq!(
+            crate::path::parse_path("[id=\"a]b\"]"),
+            vec![PathSegment::Keyed("id".to_string(), "\"a]b\"".to_string())]
+        );
+
+        let json1 = json!({"users": [{"id": 1, "name": "a", "age": 1}, {"id": 2, "name": "b", "age": 2}, {"id": 3, "name": "c"}]});
+        let json2 = json!({"users": [{"id": 2, "name": "B", "age": 3}, {"id": 4, "name": "d"}, {"id": 1, "name": "a", "age": 1}]});
+
+        let names = DeepDiff::new().array_key("id").only_paths(vec!["users[*].name".to_string()]);
+        let paths: Vec<_> = names.compare_json(&json1, &json2).iter().map(|diff| diff.path().to_string()).collect();
+        assert_eq!(paths, vec!["users[id=2].name"]);
+
+        let one_user = DeepDiff::new().array_key("id").only_paths(vec!["users[id=2]".to_string()]);
+        assert_eq!(one_user.compare_json(&json1, &json2).len(), 2);
+
+        let shallow = DeepDiff::new().array_key("id").max_depth(2);
+        let paths: Vec<_> = shallow.compare_json(&json1, &json2).iter().map(|diff| diff.path().to_string()).collect();
+        assert_eq!(paths, vec!["users[id=2]", "users[id=3]", "users[id=4]"]);
+
+        let deep_diff = DeepDiff::new().array_key("id");
+        let (v1, v2) = (deep_diff.json_to_value(&json1), deep_diff.json_to_value(&json2));
+        let rebuilt = apply(&v1, &deep_diff.compare(&v1, &v2)).unwrap();
+        assert!(deep_diff.compare(&rebuilt, &v2).is_empty());
+        assert_eq!(v2.get_path("users[id=4].name"), Ok(&Value::String("d".to_string())));
+    }
+
+    #[test]
+    fn test_order_settings_per_depth() {
+        let m1 = json!({"m": [[1, 2, 3], [4, 5]]});
+        let rows_shuffled = json!({"m": [[3, 1, 2], [5, 4]]});
+        let outer_shuffled = json!({"m": [[4, 5], [1, 2, 3]]});
+
+        let inner_unordered = DeepDiff::new().order_settings_per_depth(vec![false, true]);
+        assert!(inner_unordered.compare_json(&m1, &rows_shuffled).is_empty());
+        assert!(!inner_unordered.compare_json(&m1, &outer_shuffled).is_empty());
+
+        let outer_unordered = DeepDiff::new().order_settings_per_depth(vec![true, false]);
+        assert!(outer_unordered.compare_json(&m1, &outer_shuffled).is_empty());
+        let paths: Vec<_> = outer_unordered
+            .compare_json(&m1, &rows_shuffled)
+            .iter()
+            .map(|diff| diff.path().to_string())
+            .collect();
+        assert!(!paths.is_empty());
+        assert!(paths.iter().all(|path| path.starts_with("m[")), "{:?}", paths);
+    }
+
+    #[test]
+    fn test_lcs_front_insertion() {
+        let v1 = json!([{"n": 1}, {"n": 2}, {"n": 3}, {"n": 4}, {"n": 5}]);
+        let v2 = json!([{"n": 0}, {"n": 1}, {"n": 2}, {"n": 3}, {"n": 4}, {"n": 5}]);
+
+        let deep_diff = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs);
+        assert_eq!(
+            deep_diff.compare_json(&v1, &v2),
+            vec![Diff::Added("[0]".to_string(), deep_diff.json_to_value(&json!({"n": 0})))]
+        );
+        assert_eq!(DeepDiff::new().compare_json(&v1, &v2).len(), 6);
+    }
+
+    #[test]
+    fn test_compare_with_tolerance_report() {
+        let deep_diff = DeepDiff::new().float_tolerance(0.01, false);
+        let v1 = deep_diff.json_to_value(&json!({"a": 1.0, "b": [2.0, 3.0], "c": 5.0, "d": 7.0}));
+        let v2 = deep_diff.json_to_value(&json!({"a": 1.005, "b": [2.0, 3.001], "c": 6.0, "d": 7.0}));
+
+        let (diffs, tolerated) = deep_diff.compare_with_tolerance_report(&v1, &v2);
+        assert_eq!(
+            diffs,
+            vec![Diff::Changed("c".to_string(), Value::Float(5.0), Value::Float(6.0))]
+        );
+        assert_eq!(tolerated, vec!["a", "b[1]"]);
+
+        let (_, tolerated) = DeepDiff::new().compare_with_tolerance_report(&v1, &v2);
+        assert!(tolerated.is_empty());
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let capped = DeepDiff::new()
+            .float_tolerance(0.01, false)
+            .max_diffs(1)
+            .on_metrics(Arc::new(move |_: &crate::DiffMetrics| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }));
+        let v3 = capped.json_to_value(&json!({"a": 1.005, "b": [2.0, 3.001], "c": 6.0, "d": 8.0}));
+        let (diffs, tolerated) = capped.compare_with_tolerance_report(&v1, &v3);
+        assert_eq!(diffs, vec![Diff::Changed("c".to_string(), Value::Float(5.0), Value::Float(6.0))]);
+        assert_eq!(tolerated, vec!["a", "b[1]"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_case_insensitive_values() {
+        let deep_diff = DeepDiff::new().case_insensitive_values(true);
+        let compare = |json1: JsonValue, json2: JsonValue| deep_diff.compare_json(&json1, &json2);
+
+        assert!(compare(json!({"a": "Hello"}), json!({"a": "hello"})).is_empty());
+        assert!(compare(json!({"a": ""}), json!({"a": ""})).is_empty());
+        assert_eq!(compare(json!({"a": ""}), json!({"a": " "})).len(), 1);
+        assert!(compare(json!(["İ"]), json!(["i\u{307}"])).is_empty());
+        assert_eq!(compare(json!(["İ"]), json!(["i"])).len(), 1);
+
+        // Keys and non-string values are still compared exactly.
+        assert_eq!(compare(json!({"Key": 1}), json!({"key": 1})).len(), 2);
+        assert_eq!(compare(json!({"a": true}), json!({"a": "TRUE"})).len(), 1);
+        assert_eq!(DeepDiff::new().compare_json(&json!({"a": "Hello"}), &json!({"a": "hello"})).len(), 1);
+    }
+
+    #[test]
+    fn test_has_changes() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": [1, 2], "b": 1.0, "t": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [2, 1], "b": 1.05, "t": 2}));
+        assert!(deep_diff.has_changes(&v1, &v2));
+        assert!(!deep_diff.has_changes(&v1, &v1.clone()));
+
+        let lenient = DeepDiff::new()
+            .ignore_order(true)
+            .float_tolerance(0.1, false)
+            .exclude_paths(vec!["t".to_string()]);
+        assert!(!lenient.has_changes(&v1, &v2));
+        assert!(!DeepDiff::new()
+            .ignore_order(true)
+            .float_tolerance(0.1, false)
+            .ignore_keys(vec!["t".to_string()])
+            .has_changes(&v1, &v2));
+
+        let json1: JsonValue = (0..1000).map(|i| (format!("k{:04}", i), json!([i]))).collect::<serde_json::Map<_, _>>().into();
+        let json2: JsonValue = (0..1000).map(|i| (format!("k{:04}", i), json!([-i - 1]))).collect::<serde_json::Map<_, _>>().into();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let counting = DeepDiff::new().array_identity(Arc::new(move |value: &Value| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            value.clone()
+        }));
+        let (v1, v2) = (counting.json_to_value(&json1), counting.json_to_value(&json2));
+        assert!(counting.has_changes(&v1, &v2));
+        assert!(calls.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn test_max_output_bytes() {
+        let v1 = JsonValue::Object((0..200).map(|i| (format!("key{}", i), json!(i))).collect());
+        let v2 = JsonValue::Object((0..200).map(|i| (format!("key{}", i), json!(-i - 1))).collect());
+        let full = DeepDiff::new().compare_json(&v1, &v2);
+        assert_eq!(full.len(), 200);
+
+        let budget = 1000;
+        let deep_diff = DeepDiff::new().max_output_bytes(budget);
+        let (v1, v2) = (deep_diff.json_to_value(&v1), deep_diff.json_to_value(&v2));
+        let capped = deep_diff.compare_capped(&v1, &v2);
+        assert!(capped.truncated);
+        assert!(!capped.diffs.is_empty());
+        assert_eq!(capped.diffs, &full[..capped.diffs.len()]);
+        let kept_len = serde_json::to_string(&diffs_to_json(&capped.diffs)).unwrap().len();
+        assert!(kept_len <= budget, "{}", kept_len);
+        assert_eq!(deep_diff.compare(&v1, &v2), capped.diffs);
+        assert!(apply(&v1, &capped.diffs).is_ok());
+
+        let roomy = DeepDiff::new().max_output_bytes(1_000_000);
+        assert_eq!(roomy.compare(&v1, &v2), full);
+        assert!(!roomy.compare_capped(&v1, &v2).truncated);
+    }
+
+    #[test]
+    fn test_compare_to_map() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": {"b": 1}, "c": [1, 2], "d": "x"}));
+        let v2 = deep_diff.json_to_value(&json!({"a": {"b": 2}, "c": [1], "e": true}));
+
+        let map = deep_diff.compare_to_map(&v1, &v2);
+        assert_eq!(map.len(), 4);
+        assert_eq!(map["a.b"], Diff::Changed("a.b".to_string(), Value::Int(1), Value::Int(2)));
+        assert_eq!(map["c[1]"], Diff::Removed("c[1]".to_string(), Value::Int(2)));
+        assert!(matches!(map.get("e"), Some(Diff::Added(..))));
+        assert!(!map.contains_key("a"));
+
+        // A removal and an addition at one index merge into a change.
+        let lcs = DeepDiff::new().array_algorithm(ArrayAlgo::Lcs);
+        let v3 = lcs.json_to_value(&json!(["a", "b", "c"]));
+        let v4 = lcs.json_to_value(&json!(["a", "x", "c"]));
+        assert_eq!(lcs.compare(&v3, &v4).len(), 2);
+        assert_eq!(
+            lcs.compare_to_map(&v3, &v4).into_values().collect::<Vec<_>>(),
+            vec![Diff::Changed("[1]".to_string(), Value::String("b".to_string()), Value::String("x".to_string()))]
+        );
+    }
+
+    #[test]
+    fn test_max_diffs() {
+        let v1 = JsonValue::Object((0..50_000).map(|i| (format!("a{}", i), json!({"x": i}))).collect());
+        let v2 = JsonValue::Object((0..50_000).map(|i| (format!("a{}", i), json!({"x": -i - 1}))).collect());
+
+        let seen = Arc::new(AtomicUsize::new(0));
+        let nodes = seen.clone();
+        let deep_diff = DeepDiff::new()
+            .max_diffs(100)
+            .on_metrics(Arc::new(move |metrics: &crate::DiffMetrics| {
+                nodes.store(metrics.nodes, Ordering::SeqCst);
+            }));
+        let v1 = deep_diff.json_to_value(&v1);
+        let v2 = deep_diff.json_to_value(&v2);
+
+        let capped = deep_diff.compare_capped(&v1, &v2);
+        assert_eq!(capped.diffs.len(), 100);
+        assert!(capped.truncated);
+        assert_eq!(capped.diffs[0], Diff::Changed("a0.x".to_string(), Value::Int(0), Value::Int(-1)));
+        // The root, then each key and its `x` until the 101st diff.
+        assert_eq!(seen.load(Ordering::SeqCst), 1 + 101 * 2);
+        assert_eq!(deep_diff.compare(&v1, &v2).len(), 100);
+
+        let exact = DeepDiff::new().max_diffs(2);
+        let small1 = exact.json_to_value(&json!({"a": 1, "b": 2}));
+        let small2 = exact.json_to_value(&json!({"a": 3, "b": 4}));
+        assert!(!exact.compare_capped(&small1, &small2).truncated);
+        assert!(DeepDiff::new().max_diffs(1).compare_capped(&small1, &small2).truncated);
+    }
+
+    #[test]
+    fn test_normalize_key() {
+        let snake_case = |key: &str| {
+            key.chars().fold(String::new(), |mut out, c| {
+                if c.is_uppercase() {
+                    out.push('_');
+                }
+                out.extend(c.to_lowercase());
+                out
+            })
+        };
+        let deep_diff = DeepDiff::new().normalize_key(Arc::new(snake_case));
+        let v1 = deep_diff.json_to_value(&json!({"user_name": "a", "profile": {"last_login": 1}}));
+        let v2 = deep_diff.json_to_value(&json!({"userName": "a", "profile": {"lastLogin": 2}}));
+
+        assert_eq!(
+            deep_diff.compare(&v1, &v2),
+            vec![Diff::Changed("profile.last_login".to_string(), Value::Int(1), Value::Int(2))]
+        );
+        assert_eq!(DeepDiff::new().compare(&v1, &v2).len(), 4);
+
+        // `userName` and `user_name` collide in v2; the first in key order is matched.
+        let v3 = deep_diff.json_to_value(&json!({"userName": "a", "user_name": "b"}));
+        assert_eq!(
+            deep_diff.compare(&v1, &v3),
+            vec![
+                Diff::Removed("profile".to_string(), v1.get_path("profile").unwrap().clone()),
+                Diff::Added("user_name".to_string(), Value::String("b".to_string())),
+            ]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let obj1: serde_json::Map<String, JsonValue> = (0..20_000)
+            .map(|i| (format!("key{}", i), json!({"id": i, "tags": [i, i + 1], "score": i as f64 / 3.0})))
+            .collect();
+        let mut obj2 = obj1.clone();
+        for i in (0..20_000).step_by(7) {
+            obj2[&format!("key{}", i)]["tags"][1] = json!(-1);
+        }
+        for i in (0..20_000).step_by(11) {
+            obj2.remove(&format!("key{}", i));
+        }
+        obj2.insert("extra".to_string(), json!({"nested": true}));
+
+        let sequential = DeepDiff::new().float_tolerance(0.001, false);
+        let parallel = DeepDiff::new().float_tolerance(0.001, false).parallel(true);
+        let v1 = sequential.json_to_value(&JsonValue::Object(obj1));
+        let v2 = sequential.json_to_value(&JsonValue::Object(obj2));
+
+        let expected = sequential.compare(&v1, &v2);
+        let actual = parallel.compare(&v1, &v2);
+
+        assert!(expected.len() > 4000);
+        assert_eq!(actual, expected);
+        assert_eq!(
+            parallel.compare_with_tolerance_report(&v1, &v2),
+            sequential.compare_with_tolerance_report(&v1, &v2)
+        );
+        assert_eq!(parallel.first_diff(&v1, &v2), sequential.first_diff(&v1, &v2));
+
+        // Element probes must see changes under excluded paths, on any thread.
+        let element1: serde_json::Map<String, JsonValue> = (0..300).map(|i| (format!("k{}", i), json!(i))).collect();
+        let mut element2 = element1.clone();
+        element2.insert("k5".to_string(), json!(-1));
+        let json1 = json!({"items": [JsonValue::Object(element1)]});
+        let json2 = json!({"items": [JsonValue::Object(element2)]});
+        let excluded = vec!["items[*].k5".to_string()];
+        let sequential = DeepDiff::new().ignore_order(true).exclude_paths(excluded.clone());
+        let parallel = DeepDiff::new().ignore_order(true).exclude_paths(excluded).parallel(true);
+        let expected = sequential.compare_json(&json1, &json2);
+        assert_eq!(expected.len(), 2);
+        assert_eq!(parallel.compare_json(&json1, &json2), expected);
+    }
+
+    #[test]
+    fn test_compare_to_channel() {
+        let deep_diff = DeepDiff::new();
+        let v1 = deep_diff.json_to_value(&json!({"a": [1, 2, 3], "b": {"c": "x"}, "d": 1}));
+        let v2 = deep_diff.json_to_value(&json!({"a": [1, 5], "b": {"c": "y", "e": null}, "f": true}));
+        let expected = deep_diff.compare(&v1, &v2);
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        let consumer = std::thread::spawn(move || {
+            rx.iter()
+                .inspect(|_| std::thread::sleep(Duration::from_millis(1)))
+                .collect::<Vec<Diff>>()
+        });
+        assert_eq!(deep_diff.compare_to_channel(&v1, &v2, tx), Ok(()));
+        assert_eq!(consumer.join().unwrap(), expected);
+
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        drop(rx);
+        let err = deep_diff.compare_to_channel(&v1, &v2, tx).unwrap_err();
+        assert_eq!(err.0, expected[0]);
+    }
+
+    #[test]
+    fn test_compare_iter() {
+        let json1 = json!({"a": [1, 2, 3], "b": {"c": "x", "g": [{"h": 1}]}, "d": 1, "i": {"j": null}});
+        let json2 = json!({"a": [3, 5], "b": {"c": "y", "e": null, "g": [{"h": 2}]}, "f": true, "i": 1});
+        for deep_diff in [
+            DeepDiff::new(),
+            DeepDiff::new().ignore_order(true),
+            DeepDiff::new().array_algorithm(ArrayAlgo::Lcs),
+            DeepDiff::new().ignore_keys(vec!["c".to_string()]).max_diffs(3),
+        ] {
+            let v1 = deep_diff.json_to_value(&json1);
+            let v2 = deep_diff.json_to_value(&json2);
+            assert_eq!(deep_diff.compare_iter(&v1, &v2).collect::<Vec<_>>(), deep_diff.compare(&v1, &v2));
+        }
+    }
+
+    #[test]
+    fn test_compare_iter_is_lazy() {
+        let json1: JsonValue = (0..10_000).map(|i| (format!("k{:05}", i), json!([{"n": i}]))).collect::<serde_json::Map<_, _>>().into();
+        let json2: JsonValue = (0..10_000).map(|i| (format!("k{:05}", i), json!([{"n": -i - 1}]))).collect::<serde_json::Map<_, _>>().into();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let deep_diff = DeepDiff::new().array_identity(Arc::new(move |value: &Value| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            value.clone()
+        }));
+        let v1 = deep_diff.json_to_value(&json1);
+        let v2 = deep_diff.json_to_value(&json2);
+
+        let first: Vec<Diff> = deep_diff.compare_iter(&v1, &v2).take(5).collect();
+        assert!(calls.load(Ordering::SeqCst) < 100);
+        assert_eq!(first, deep_diff.compare(&v1, &v2)[..5]);
+    }
+
+    #[test]
+    fn test_element_matching_ignores_root_options() {
+        let json1 = json!({"items": [{"id": 1}]});
+        let json2 = json!({"items": [{"id": 2}]});
+        let expected = vec![
+            Diff::Removed("items[0]".to_string(), DeepDiff::new().json_to_value(&json!({"id": 1}))),
+            Diff::Added("items[0]".to_string(), DeepDiff::new().json_to_value(&json!({"id": 2}))),
+        ];
+
+        let top_level = DeepDiff::new().top_level_keys(&["items"]).ignore_order(true);
+        assert_eq!(top_level.compare_json(&json1, &json2), expected);
+        let only = DeepDiff::new().only_paths(vec!["items".to_string()]).ignore_order(true);
+        assert_eq!(only.compare_json(&json1, &json2), expected);
+        assert_eq!(only.compare_json(&json!({"items": [1]}), &json!({"items": [2]})).len(), 2);
+
+        let reorders = DeepDiff::new()
+            .only_paths(vec!["items".to_string()])
+            .ignore_order(true)
+            .report_reorders(true);
+        let diffs = reorders.compare_json(&json!({"items": [{"v": 2}, {"v": 1}]}), &json!({"items": [{"v": 1}, {"v": 3}]}));
+        assert!(!diffs.iter().any(|diff| matches!(diff, Diff::Reordered(_))), "{:?}", diffs);
+        assert_eq!(diffs.len(), 2);
+    }
+
+    #[test]
+    fn test_compare_shared() {
+        let deep_diff = DeepDiff::new();
+        let mut document = serde_json::Map::new();
+        for i in 0..100 {
+            let section: serde_json::Map<String, JsonValue> = (0..1000).map(|j| (format!("k{}", j), json!(j))).collect();
+            document.insert(format!("s{}", i), JsonValue::Object(section));
+        }
+        let v1 = Arc::new(SharedValue::from(deep_diff.json_to_value(&JsonValue::Object(document))));
+
+        // The next version reuses every subtree but one leaf.
+        let SharedValue::Dict(sections) = &*v1 else { unreachable!() };
+        let mut sections = sections.clone();
+        let SharedValue::Dict(section) = &*sections["s5"] else { unreachable!() };
+        let mut section = section.clone();
+        section.insert("k7".to_string(), Arc::new(SharedValue::Value(Value::Int(-1))));
+        section.remove("k8");
+        sections.insert("s5".to_string(), Arc::new(SharedValue::Dict(section)));
+        let v2 = Arc::new(SharedValue::Dict(sections));
+
+        let (plain1, plain2) = (v1.to_value(), v2.to_value());
+        let start = Instant::now();
+        let expected = deep_diff.compare(&plain1, &plain2);
+        let plain_elapsed = start.elapsed();
+        let start = Instant::now();
+        let diffs = deep_diff.compare_shared(&v1, &v2);
+        let shared_elapsed = start.elapsed();
+
+        assert_eq!(diffs, expected);
+        assert_eq!(diffs, vec![
+            Diff::Changed("s5.k7".to_string(), Value::Int(7), Value::Int(-1)),
+            Diff::Removed("s5.k8".to_string(), Value::Int(8)),
+        ]);
+        // Comparing the plain values walks the 99 unchanged sections; the
+        // shared ones are skipped by pointer.
+        assert!(shared_elapsed < plain_elapsed, "{:?} vs {:?}", shared_elapsed, plain_elapsed);
+        assert!(deep_diff.compare_shared(&v1, &v1).is_empty());
+
+        // Options that need whole dicts fall back to the plain values.
+        let folded = DeepDiff::new().case_insensitive_keys(true);
+        assert_eq!(folded.compare_shared(&v1, &v2), folded.compare(&plain1, &plain2));
+        let excluded = DeepDiff::new().exclude_paths(vec!["s5.k8".to_string()]);
+        assert_eq!(excluded.compare_shared(&v1, &v2), excluded.compare(&plain1, &plain2));
+    }
 }
@@ -0,0 +1,49 @@
+use crate::diff::{DeepDiff, Diff, Value};
+use serde_json::Value as JsonValue;
+use serde_yaml::Value as YamlValue;
+
+impl DeepDiff {
+    pub fn compare_yaml(&self, y1: &YamlValue, y2: &YamlValue) -> Vec<Diff> {
+        let v1 = self.yaml_to_value(y1);
+        let v2 = self.yaml_to_value(y2);
+        self.compare(&v1, &v2)
+    }
+
+    /// Converts like `json_to_value`. Tags are dropped in favour of the
+    /// tagged value, and map keys that aren't strings become strings: scalars
+    /// by their plain text (`1`, `true`, `null`), sequences and maps by their
+    /// compact JSON form.
+    pub(crate) fn yaml_to_value(&self, yaml: &YamlValue) -> Value {
+        match yaml {
+            YamlValue::Null => Value::Null,
+            YamlValue::Bool(b) => Value::Bool(*b),
+            YamlValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => Value::Int(i),
+                (None, Some(u)) => Value::UInt(u),
+                (None, None) => Value::Float(n.as_f64().unwrap()),
+            },
+            YamlValue::String(s) => Value::String(s.clone()),
+            YamlValue::Sequence(seq) => {
+                Value::Array(seq.iter().map(|v| self.yaml_to_value(v)).collect())
+            }
+            YamlValue::Mapping(map) => Value::Dict(
+                map.iter()
+                    .map(|(k, v)| (self.yaml_key(k), self.yaml_to_value(v)))
+                    .collect(),
+            ),
+            YamlValue::Tagged(tagged) => self.yaml_to_value(&tagged.value),
+        }
+    }
+
+    fn yaml_key(&self, key: &YamlValue) -> String {
+        match self.yaml_to_value(key) {
+            Value::String(s) => s,
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Int(i) => i.to_string(),
+            Value::UInt(u) => u.to_string(),
+            Value::Float(f) => f.to_string(),
+            other => JsonValue::from(other).to_string(),
+        }
+    }
+}